@@ -0,0 +1,188 @@
+//! What `sys` does to the units it found: asking first, the unit file changes of `-1`, `-0`,
+//! `--mask` and `--unmask`, and the jobs of `-S`, `-K` and `-R`.
+use std::{
+    collections::BTreeMap,
+    io,
+};
+
+use itertools::Itertools;
+use tracing::{
+    span,
+    Instrument,
+    Level,
+};
+
+use crate::{
+    journal::JournalFollower,
+    properties::PropertyCache,
+    DaemonType,
+    JobResult,
+    SysError,
+    Unit,
+    UnitAction,
+    UnitFileChange,
+};
+
+/// more matching units than this get the picker instead of the yes/no prompt on a terminal
+pub const PICK_OVER: usize = 5;
+
+/// ask on the terminal whether the actions, named like "Start", should go ahead on all the
+/// units, or with `pick` which of them should get them. False if the user said no or left none
+pub fn confirm(
+    all_units: &mut BTreeMap<DaemonType, Vec<Unit<'_>>>,
+    actions: &[&str],
+    pick: bool,
+) -> io::Result<bool> {
+    let sty = console::Style::new().bold();
+    let actions = actions
+        .iter()
+        .map(|a| sty.apply_to(a).to_string())
+        .collect_vec();
+
+    let actions_str = [
+        actions[..actions.len() - 1].join(", "),
+        actions[actions.len() - 1].to_string(),
+    ]
+    .iter()
+    .filter(|v| !v.is_empty())
+    .join(" and ");
+
+    let confirm_str = all_units
+        .iter()
+        .filter_map(|(daemon, ls)| match ls.is_empty() {
+            true => None,
+            false => Some(format!(
+                "{} {} units",
+                ls.len(),
+                daemon.to_string().to_lowercase()
+            )),
+        })
+        .join(" and ");
+
+    match pick {
+        true => pick_units(
+            all_units,
+            format!("{} which of {}?", actions_str, confirm_str),
+        ),
+        false => dialoguer::Confirm::new()
+            .with_prompt(format!("{} {}?", actions_str, confirm_str))
+            .default(false)
+            .interact(),
+    }
+}
+
+/// let the user uncheck the units that should not get the actions, false if they backed out
+/// or left none
+fn pick_units(
+    all_units: &mut BTreeMap<DaemonType, Vec<Unit<'_>>>,
+    prompt: String,
+) -> io::Result<bool> {
+    let several_daemons = all_units.values().filter(|units| !units.is_empty()).count() > 1;
+    let width = all_units
+        .values()
+        .flatten()
+        .map(|unit| console::measure_text_width(&unit.info.name))
+        .max()
+        .unwrap_or(0);
+    let items = all_units
+        .iter()
+        .flat_map(|(daemon, units)| {
+            units.iter().map(move |unit| {
+                let info = &unit.info;
+                let mut item = format!("{:width$}  {} {}", info.name, info.active, info.status);
+                if several_daemons {
+                    item += &format!(" ({})", daemon.to_string().to_lowercase());
+                }
+                (item, true)
+            })
+        })
+        .collect_vec();
+
+    let picked = dialoguer::MultiSelect::new()
+        .with_prompt(prompt)
+        .items_checked(&items)
+        .interact_opt()?;
+    let Some(picked) = picked else {
+        return Ok(false);
+    };
+
+    let mut i = 0;
+    for units in all_units.values_mut() {
+        units.retain(|_| {
+            i += 1;
+            picked.contains(&(i - 1))
+        });
+    }
+    Ok(!picked.is_empty())
+}
+
+/// the unit file changes asked for, done in this order: unmask, enable (or reenable with
+/// disable too), disable, mask
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UnitFileActions {
+    pub unmask: bool,
+    pub enable: bool,
+    pub disable: bool,
+    pub mask: bool,
+    /// only until reboot, in /run
+    pub runtime: bool,
+    pub force: bool,
+}
+
+impl UnitFileActions {
+    pub fn any(&self) -> bool {
+        self.unmask || self.enable || self.disable || self.mask
+    }
+
+    /// each action done on the unit, with the unit file it went by if any
+    pub async fn apply(
+        &self,
+        unit: &Unit<'_>,
+    ) -> Vec<(
+        &'static str,
+        Result<(Option<String>, Vec<UnitFileChange>), SysError>,
+    )> {
+        let mut done = Vec::new();
+        if self.unmask {
+            let res = unit.unmask(self.runtime).await;
+            done.push(("unmask", res.map(|changes| (None, changes))));
+        }
+        if self.enable && self.disable {
+            let res = unit.reenable(self.runtime, self.force).await;
+            done.push(("reenable", res.map(|(file, changes)| (Some(file), changes))));
+        } else if self.enable {
+            let res = unit.enable(self.runtime, self.force).await;
+            done.push(("enable", res.map(|(file, changes)| (Some(file), changes))));
+        } else if self.disable {
+            let res = unit.disable(self.runtime).await;
+            done.push(("disable", res.map(|changes| (None, changes))));
+        }
+        if self.mask {
+            let res = unit.mask(self.runtime, self.force).await;
+            done.push(("mask", res.map(|changes| (None, changes))));
+        }
+        done
+    }
+}
+
+/// run each action on every unit before the next action, `done` is told how each job went
+/// as it ends
+pub async fn run_jobs<'a>(
+    all_units: &BTreeMap<DaemonType, Vec<Unit<'a>>>,
+    actions: &[UnitAction],
+    journal: &mut JournalFollower,
+    properties: &PropertyCache,
+    mut done: impl FnMut(&Unit<'a>, UnitAction, Result<JobResult, SysError>),
+) {
+    for &action in actions {
+        async {
+            for unit in all_units.values().flatten() {
+                let result = unit.run_job(action, journal).await;
+                properties.forget(unit);
+                done(unit, action, result);
+            }
+        }
+        .instrument(span!(Level::INFO, "action", action = action.to_string()))
+        .await
+    }
+}
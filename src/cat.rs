@@ -0,0 +1,398 @@
+//! `sys cat`: the files a unit was loaded from, or with `--effective` a unit file made from
+//! what systemd has for it now, defaults and drop-ins included.
+//!
+//! Most properties are named after the directive that sets them, timespans end in USec
+//! instead of Sec, and a few lists of structs stand for several directives, like Listen for
+//! ListenStream= and friends. Properties that are state rather than settings are left out,
+//! values we can't write the way unit files do are commented out. [Install] is not on the
+//! bus, systemd reads it from the files when enabling, so we do too.
+use std::io::ErrorKind;
+
+use itertools::Itertools;
+use zbus::zvariant::Value;
+
+use crate::{
+    error::SysError,
+    properties::{
+        type_interface,
+        Properties,
+        PropertyCache,
+        UNIT_INTERFACE,
+    },
+    status::format_timespan_exact,
+    Unit,
+};
+
+/// the properties of the Unit interface that are directives of [Unit], in the order of
+/// systemd.unit(5)
+const UNIT_DIRECTIVES: [&str; 41] = [
+    "Description",
+    "Documentation",
+    "Wants",
+    "Requires",
+    "Requisite",
+    "BindsTo",
+    "PartOf",
+    "Upholds",
+    "Conflicts",
+    "Before",
+    "After",
+    "OnFailure",
+    "OnSuccess",
+    "PropagatesReloadTo",
+    "ReloadPropagatedFrom",
+    "PropagatesStopTo",
+    "StopPropagatedFrom",
+    "JoinsNamespaceOf",
+    "RequiresMountsFor",
+    "WantsMountsFor",
+    "OnFailureJobMode",
+    "IgnoreOnIsolate",
+    "StopWhenUnneeded",
+    "RefuseManualStart",
+    "RefuseManualStop",
+    "AllowIsolate",
+    "DefaultDependencies",
+    "SurviveFinalKillSignal",
+    "CollectMode",
+    "FailureAction",
+    "SuccessAction",
+    "FailureActionExitStatus",
+    "SuccessActionExitStatus",
+    "JobTimeoutUSec",
+    "JobRunningTimeoutUSec",
+    "JobTimeoutAction",
+    "StartLimitIntervalUSec",
+    "StartLimitBurst",
+    "StartLimitAction",
+    "Conditions",
+    "Asserts",
+];
+
+/// properties of the type interfaces that are state, see also [`is_state`]
+const STATE: [&str; 27] = [
+    "MainPID",
+    "ControlPID",
+    "StatusText",
+    "StatusErrno",
+    "StatusBusError",
+    "StatusVarlinkError",
+    "Result",
+    "ReloadResult",
+    "CleanResult",
+    "NRestarts",
+    "NFileDescriptorStore",
+    "UID",
+    "GID",
+    "ControlGroup",
+    "ControlGroupId",
+    "NAccepted",
+    "NConnections",
+    "NRefused",
+    "NextElapseUSecRealtime",
+    "NextElapseUSecMonotonic",
+    "LastTriggerUSec",
+    "LastTriggerUSecMonotonic",
+    "CPUUsageNSec",
+    "EffectiveCPUs",
+    "EffectiveMemoryNodes",
+    "EffectiveMemoryMax",
+    "EffectiveTasksMax",
+];
+
+/// linux capabilities by bit, for CapabilityBoundingSet= and AmbientCapabilities=
+const CAPABILITIES: [&str; 41] = [
+    "CAP_CHOWN",
+    "CAP_DAC_OVERRIDE",
+    "CAP_DAC_READ_SEARCH",
+    "CAP_FOWNER",
+    "CAP_FSETID",
+    "CAP_KILL",
+    "CAP_SETGID",
+    "CAP_SETUID",
+    "CAP_SETPCAP",
+    "CAP_LINUX_IMMUTABLE",
+    "CAP_NET_BIND_SERVICE",
+    "CAP_NET_BROADCAST",
+    "CAP_NET_ADMIN",
+    "CAP_NET_RAW",
+    "CAP_IPC_LOCK",
+    "CAP_IPC_OWNER",
+    "CAP_SYS_MODULE",
+    "CAP_SYS_RAWIO",
+    "CAP_SYS_CHROOT",
+    "CAP_SYS_PTRACE",
+    "CAP_SYS_PACCT",
+    "CAP_SYS_ADMIN",
+    "CAP_SYS_BOOT",
+    "CAP_SYS_NICE",
+    "CAP_SYS_RESOURCE",
+    "CAP_SYS_TIME",
+    "CAP_SYS_TTY_CONFIG",
+    "CAP_MKNOD",
+    "CAP_LEASE",
+    "CAP_AUDIT_WRITE",
+    "CAP_AUDIT_CONTROL",
+    "CAP_SETFCAP",
+    "CAP_MAC_OVERRIDE",
+    "CAP_MAC_ADMIN",
+    "CAP_SYSLOG",
+    "CAP_WAKE_ALARM",
+    "CAP_BLOCK_SUSPEND",
+    "CAP_AUDIT_READ",
+    "CAP_PERFMON",
+    "CAP_BPF",
+    "CAP_CHECKPOINT_RESTORE",
+];
+
+fn is_state(name: &str) -> bool {
+    const SUFFIXES: [&str; 6] = [
+        "Current",
+        "Peak",
+        "Available",
+        "Bytes",
+        "Packets",
+        "Operations",
+    ];
+    STATE.contains(&name)
+        || name.starts_with("ExecMain")
+        || name.contains("Timestamp")
+        || SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+/// the directive a property is set with, TimeoutStartSec= for TimeoutStartUSec
+fn directive(property: &str) -> String {
+    match property.strip_suffix("USec") {
+        Some(base) => format!("{base}Sec"),
+        None => property.to_string(),
+    }
+}
+
+/// a word of a list, quoted if it would split otherwise
+fn quote(word: &str) -> String {
+    match word.is_empty() || word.contains(|c: char| c.is_whitespace() || c == '"') {
+        true => format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\"")),
+        false => word.to_string(),
+    }
+}
+
+fn string(value: &Value) -> Option<String> {
+    match value {
+        Value::Str(s) => Some(s.to_string()),
+        _ => None,
+    }
+}
+
+/// the fields of each struct in an array of them, None for anything else
+fn structs<'v>(value: &'v Value) -> Option<Vec<&'v [Value<'v>]>> {
+    let Value::Array(array) = value else {
+        return None;
+    };
+    array
+        .get()
+        .iter()
+        .map(|item| match item {
+            Value::Structure(fields) => Some(fields.fields()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// the `Directive=value` lines for a property, none where it is unset
+fn directives(name: &str, value: &Value, properties: &Properties) -> Vec<String> {
+    let line = |value: String| vec![format!("{}={value}", directive(name))];
+    let commented = || vec![format!("# {}={value}", directive(name))];
+
+    // ExecStartEx and friends are the same commands with their prefixes as flags
+    if name.starts_with("Exec") && name.ends_with("Ex") {
+        return Vec::new();
+    }
+
+    // several directives in a list of structs
+    if let Some(items) = structs(value) {
+        let lines = items.into_iter().filter_map(|fields| match (name, fields) {
+            (
+                "Conditions" | "Asserts",
+                [Value::Str(kind), Value::Bool(trigger), Value::Bool(negate), Value::Str(param), ..],
+            ) => Some(format!(
+                "{kind}={}{}{param}",
+                if *trigger { "|" } else { "" },
+                if *negate { "!" } else { "" }
+            )),
+            (_, [Value::Str(path), Value::Array(argv), Value::Bool(ignore), ..])
+                if name.starts_with("Exec") =>
+            {
+                let argv = argv.get().iter().filter_map(string).collect_vec();
+                let command = argv.iter().map(|word| quote(word)).join(" ");
+                // @ when argv[0] is not the binary
+                let command = match argv.first().map(String::as_str) == Some(path.as_str()) {
+                    true => command,
+                    false => format!("@{path} {command}"),
+                };
+                Some(format!("{name}={}{command}", if *ignore { "-" } else { "" }))
+            }
+            ("TimersCalendar", [Value::Str(base), Value::Str(spec), ..]) => {
+                Some(format!("{base}={spec}"))
+            }
+            ("TimersMonotonic", [Value::Str(base), Value::U64(usec), ..]) => Some(format!(
+                "{}={}",
+                directive(base),
+                format_timespan_exact(*usec)
+            )),
+            ("Listen", [Value::Str(kind), Value::Str(address)]) => {
+                Some(format!("Listen{kind}={address}"))
+            }
+            ("Paths", [Value::Str(kind), Value::Str(path)]) => Some(format!("{kind}={path}")),
+            _ => None,
+        });
+        let lines = lines.collect_vec();
+        return match lines.is_empty() && !matches!(value, Value::Array(a) if a.get().is_empty()) {
+            true => commented(),
+            false => lines,
+        };
+    }
+
+    match (name, value) {
+        // folded into the hard limit, soft:hard
+        (_, _) if name.starts_with("Limit") && name.ends_with("Soft") => Vec::new(),
+        (_, Value::U64(hard)) if name.starts_with("Limit") => {
+            let limit = |n: u64| match n {
+                u64::MAX => "infinity".to_string(),
+                n => n.to_string(),
+            };
+            match properties.get::<u64>(&format!("{name}Soft")) {
+                Some(soft) if soft != *hard => line(format!("{}:{}", limit(soft), limit(*hard))),
+                _ => line(limit(*hard)),
+            }
+        }
+        ("UMask", Value::U32(mask)) => line(format!("{mask:04o}")),
+        ("CapabilityBoundingSet" | "AmbientCapabilities", Value::U64(set)) => {
+            let names = CAPABILITIES
+                .iter()
+                .enumerate()
+                .filter(|(bit, _)| set & (1 << bit) != 0)
+                .map(|(_, name)| *name)
+                .collect_vec();
+            // all of them is what not setting it means
+            match names.is_empty() || names.len() == CAPABILITIES.len() {
+                true => Vec::new(),
+                false => line(names.join(" ")),
+            }
+        }
+        ("CPUQuotaPerSecUSec", Value::U64(u64::MAX)) => Vec::new(),
+        ("CPUQuotaPerSecUSec", Value::U64(usec)) => vec![format!("CPUQuota={}%", usec / 10_000)],
+        // unset, not infinite
+        (_, Value::U64(u64::MAX)) if name.ends_with("Weight") || name.ends_with("Shares") => {
+            Vec::new()
+        }
+        (_, Value::U64(u64::MAX)) => line("infinity".to_string()),
+        (_, Value::U64(usec)) if name.ends_with("USec") => line(format_timespan_exact(*usec)),
+        (_, Value::Bool(yes)) => line(if *yes { "yes" } else { "no" }.to_string()),
+        (_, Value::Str(s)) if s.is_empty() => Vec::new(),
+        (_, Value::Str(s)) => line(s.to_string()),
+        (_, Value::U8(n)) => line(n.to_string()),
+        (_, Value::I16(n)) => line(n.to_string()),
+        (_, Value::U16(n)) => line(n.to_string()),
+        (_, Value::I32(n)) => line(n.to_string()),
+        (_, Value::U32(n)) => line(n.to_string()),
+        (_, Value::I64(n)) => line(n.to_string()),
+        (_, Value::U64(n)) => line(n.to_string()),
+        (_, Value::F64(n)) => line(n.to_string()),
+        (_, Value::Array(array)) if array.get().is_empty() => Vec::new(),
+        (_, Value::Array(array)) => {
+            match array.get().iter().map(string).collect::<Option<Vec<_>>>() {
+                Some(words) => line(words.iter().map(|word| quote(word)).join(" ")),
+                None => commented(),
+            }
+        }
+        (_, Value::ObjectPath(_) | Value::Signature(_)) => Vec::new(),
+        _ => commented(),
+    }
+}
+
+fn read(path: &str) -> Result<String, SysError> {
+    std::fs::read_to_string(path).map_err(|e| match e.kind() {
+        ErrorKind::PermissionDenied => SysError::Permission(format!("reading {path}")),
+        _ => SysError::NotFound(format!("{path}: {e}")),
+    })
+}
+
+/// The unit file and drop-ins systemd loaded the unit from, in the order it read them, each
+/// with its text.
+///
+/// Generated units without a fragment, like mounts from /etc/fstab, have their source
+/// instead.
+pub async fn files(
+    unit: &Unit<'_>,
+    cache: &PropertyCache,
+) -> Result<Vec<(String, Result<String, SysError>)>, SysError> {
+    let properties = cache.get(unit, UNIT_INTERFACE).await?;
+    let path = |name: &str| properties.get::<String>(name).filter(|p| !p.is_empty());
+    let drop_ins = properties
+        .get::<Vec<String>>("DropInPaths")
+        .unwrap_or_default();
+    Ok(path("FragmentPath")
+        .or_else(|| path("SourcePath"))
+        .into_iter()
+        .chain(drop_ins)
+        .map(|path| {
+            let text = read(&path);
+            (path, text)
+        })
+        .collect())
+}
+
+/// the [Install] lines of the files in the order systemd reads them
+fn install_lines(files: &[(String, Result<String, SysError>)]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for text in files.iter().filter_map(|(_, text)| text.as_ref().ok()) {
+        let mut install = false;
+        for line in text.lines().map(str::trim) {
+            if line.starts_with('[') {
+                install = line == "[Install]";
+            } else if install && !line.is_empty() && !line.starts_with(['#', ';']) {
+                lines.push(line.to_string());
+            }
+        }
+    }
+    lines
+}
+
+/// the unit as a unit file with every setting it has now, a section per interface
+pub async fn effective(unit: &Unit<'_>, cache: &PropertyCache) -> Result<String, SysError> {
+    let mut sections = Vec::new();
+
+    let properties = cache.get(unit, UNIT_INTERFACE).await?;
+    let lines = UNIT_DIRECTIVES
+        .iter()
+        .filter_map(|name| Some((name, properties.value(name)?)))
+        .flat_map(|(name, value)| directives(name, value, &properties))
+        .collect_vec();
+    sections.push(("Unit".to_string(), lines));
+
+    // types like target have nothing of their own
+    let interface = type_interface(&unit.info.unit_type);
+    if let Ok(properties) = cache.get(unit, &interface).await {
+        let lines = properties
+            .iter()
+            .filter(|(name, _)| !is_state(name))
+            .sorted_by_key(|(name, _)| *name)
+            .flat_map(|(name, value)| directives(name, value, &properties))
+            .collect_vec();
+        let section = interface.rsplit('.').next().unwrap_or_default();
+        sections.push((section.to_string(), lines));
+    }
+
+    let install = install_lines(&files(unit, cache).await?);
+    sections.push(("Install".to_string(), install));
+
+    let sections = sections
+        .into_iter()
+        .filter(|(_, lines)| !lines.is_empty())
+        .map(|(section, lines)| format!("[{section}]\n{}\n", lines.join("\n")))
+        .join("\n");
+    Ok(format!(
+        "# {} as systemd has it, with defaults and drop-ins\n{sections}",
+        unit.info.name
+    ))
+}
@@ -0,0 +1,293 @@
+//! `--columns` and `--sort`, and the unit properties beyond what ListUnits gives us that
+//! they can ask for. Those cost a GetAll per unit and interface, so only the interfaces of
+//! wanted ones are read, and only units systemd has loaded have them.
+use std::cmp::Ordering;
+
+use clap::ValueEnum;
+use itertools::Itertools;
+
+use crate::{
+    properties::{
+        for_each_unit,
+        type_interface,
+        PropertyCache,
+        UNIT_INTERFACE,
+    },
+    status::{
+        colorize_status,
+        format_bytes,
+        format_timestamp,
+    },
+    TypeOpt,
+    Unit,
+};
+
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq)]
+pub enum Column {
+    Daemon,
+    /// load, active and sub state by their first letters
+    States,
+    Type,
+    /// with the type as suffix
+    Name,
+    BaseName,
+    Load,
+    Active,
+    Sub,
+    Description,
+    MainPid,
+    Memory,
+    UnitFileState,
+    FragmentPath,
+    ActiveEnter,
+}
+
+/// the table without `--columns`, before hiding what `-v` and friends don't ask for
+pub const DEFAULT_COLUMNS: [Column; 10] = [
+    Column::Daemon,
+    Column::States,
+    Column::Type,
+    Column::Name,
+    Column::BaseName,
+    Column::Load,
+    Column::Active,
+    Column::Sub,
+    Column::UnitFileState,
+    Column::Description,
+];
+
+/// unit files nobody loaded yet are dimmed
+fn dim_unloaded(unit: &Unit, name: &str) -> String {
+    match unit.proxy {
+        Some(_) => name.to_string(),
+        None => console::style(name).dim().to_string(),
+    }
+}
+
+impl Column {
+    /// whether it reads unit properties, and so needs the unit loaded
+    pub fn reads_properties(&self) -> bool {
+        matches!(
+            self,
+            Column::MainPid | Column::Memory | Column::FragmentPath | Column::ActiveEnter
+        )
+    }
+
+    pub fn header(&self) -> &'static str {
+        match self {
+            Column::Daemon => "DAEMON",
+            Column::States => "L-A-S",
+            Column::Type => "TYPE",
+            Column::Name => "UNIT",
+            Column::BaseName => "NAME",
+            Column::Load => "LOAD",
+            Column::Active => "ACTIVE",
+            Column::Sub => "SUB",
+            Column::Description => "DESCRIPTION",
+            Column::MainPid => "PID",
+            Column::Memory => "MEMORY",
+            Column::UnitFileState => "UNIT FILE",
+            Column::FragmentPath => "PATH",
+            Column::ActiveEnter => "ACTIVE SINCE",
+        }
+    }
+
+    /// the cell for this unit, styled for the terminal
+    pub fn render(&self, unit: &Unit, details: &Details) -> String {
+        let state = |state: String| colorize_status(&state).apply_to(&state).to_string();
+        let initial = |state: String| {
+            let initial = state.chars().next().unwrap_or('?');
+            colorize_status(&state).apply_to(initial).to_string()
+        };
+        let name = |name: &str| dim_unloaded(unit, name);
+        let info = &unit.info;
+        match self {
+            Column::Daemon => console::style(format!("{}:", unit.daemon))
+                .dim()
+                .to_string(),
+            Column::States => [
+                initial(info.loaded.to_string()),
+                initial(info.active.to_string()),
+                initial(info.status.to_string()),
+            ]
+            .join("-"),
+            Column::Type => info.unit_type.color_str(false).to_string(),
+            Column::Name => format!(
+                "{}{}",
+                name(&info.base_name),
+                info.unit_type.color_str(true)
+            ),
+            Column::BaseName => name(&info.base_name),
+            Column::Load => state(info.loaded.to_string()),
+            Column::Active => state(info.active.to_string()),
+            Column::Sub => state(info.status.to_string()),
+            Column::Description => console::style(&info.desc).italic().to_string(),
+            Column::MainPid => details
+                .main_pid
+                .map(|pid| pid.to_string())
+                .unwrap_or_default(),
+            Column::Memory => details.memory.map(format_bytes).unwrap_or_default(),
+            Column::UnitFileState => info
+                .unit_file_state
+                .map(|file| state(file.to_string()))
+                .unwrap_or_default(),
+            Column::FragmentPath => details.fragment_path.clone().unwrap_or_default(),
+            Column::ActiveEnter => details
+                .active_enter
+                .and_then(|usec| format_timestamp(usec, "%Y-%m-%d %H:%M:%S"))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// for an instance right under its template, the name columns only show the instance
+    pub fn render_instance(&self, unit: &Unit, details: &Details) -> String {
+        let Some(instance) = unit.info.instance() else {
+            return self.render(unit, details);
+        };
+        let instance = dim_unloaded(unit, &format!("@{instance}"));
+        match self {
+            Column::Name => format!("  └ {instance}{}", unit.info.unit_type.color_str(true)),
+            Column::BaseName => format!("  └ {instance}"),
+            _ => self.render(unit, details),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    /// active state, then sub state
+    State,
+    Type,
+    Daemon,
+    /// the most first
+    Memory,
+    /// the latest state change first
+    Changed,
+}
+
+impl SortKey {
+    /// whether it reads unit properties, and so needs the unit loaded
+    pub fn reads_properties(&self) -> bool {
+        matches!(self, SortKey::Memory | SortKey::Changed)
+    }
+
+    /// ties go by daemon and then name, like the table without `--sort`
+    pub fn compare(&self, a: (&Unit, &Details), b: (&Unit, &Details)) -> Ordering {
+        let ((a, a_details), (b, b_details)) = (a, b);
+        let key = match self {
+            SortKey::Name => a.info.name.cmp(&b.info.name),
+            SortKey::State => {
+                (&a.info.active, &a.info.status).cmp(&(&b.info.active, &b.info.status))
+            }
+            SortKey::Type => a.info.unit_type.cmp(&b.info.unit_type),
+            SortKey::Daemon => Ordering::Equal,
+            // reversed, and None is the least
+            SortKey::Memory => b_details.memory.cmp(&a_details.memory),
+            SortKey::Changed => b_details.state_change.cmp(&a_details.state_change),
+        };
+        key.then_with(|| a.daemon.cmp(&b.daemon))
+            .then_with(|| a.info.name.cmp(&b.info.name))
+    }
+}
+
+/// properties of a unit that are not in ListUnits, None if not there or not read
+#[derive(Debug, Clone, Default)]
+pub struct Details {
+    pub main_pid: Option<u32>,
+    pub memory: Option<u64>,
+    pub fragment_path: Option<String>,
+    /// usec since the epoch
+    pub active_enter: Option<u64>,
+    /// usec since the epoch
+    pub state_change: Option<u64>,
+}
+
+/// unit types with a cgroup, which is where MemoryCurrent lives
+const CGROUP_TYPES: [TypeOpt; 6] = [
+    TypeOpt::Service,
+    TypeOpt::Socket,
+    TypeOpt::Mount,
+    TypeOpt::Swap,
+    TypeOpt::Slice,
+    TypeOpt::Scope,
+];
+
+impl Details {
+    pub async fn fetch(
+        unit: &Unit<'_>,
+        columns: &[Column],
+        sort: Option<SortKey>,
+        cache: &PropertyCache,
+    ) -> Self {
+        let wants = |column: Column| columns.contains(&column);
+        let mut details = Details::default();
+        if unit.proxy.is_none() {
+            return details;
+        }
+
+        if wants(Column::FragmentPath)
+            || wants(Column::ActiveEnter)
+            || sort == Some(SortKey::Changed)
+        {
+            if let Ok(properties) = cache.get(unit, UNIT_INTERFACE).await {
+                details.fragment_path = properties
+                    .get::<String>("FragmentPath")
+                    .filter(|path| !path.is_empty());
+                details.active_enter = properties.get("ActiveEnterTimestamp");
+                details.state_change = properties.get("StateChangeTimestamp");
+            }
+        }
+
+        let unit_type = &unit.info.unit_type;
+        let main_pid = wants(Column::MainPid) && *unit_type == TypeOpt::Service;
+        let memory = (wants(Column::Memory) || sort == Some(SortKey::Memory))
+            && CGROUP_TYPES.contains(unit_type);
+        if main_pid || memory {
+            if let Ok(properties) = cache.get(unit, &type_interface(unit_type)).await {
+                details.main_pid = properties.get::<u32>("MainPID").filter(|pid| *pid != 0);
+                // u64::MAX when memory accounting is off
+                details.memory = properties
+                    .get::<u64>("MemoryCurrent")
+                    .filter(|bytes| *bytes != u64::MAX);
+            }
+        }
+        details
+    }
+}
+
+/// the units of every daemon with what the columns and sort key need, in table order.
+/// Without a sort key that is by daemon and name, with instances right after their template
+pub async fn rows<'u, 'a>(
+    units: impl Iterator<Item = &'u Unit<'a>>,
+    columns: &[Column],
+    sort: Option<SortKey>,
+    cache: &PropertyCache,
+) -> Vec<(&'u Unit<'a>, Details)>
+where
+    'a: 'u,
+{
+    let units = units.collect_vec();
+    let details = for_each_unit(units.iter().copied(), |unit| {
+        Details::fetch(unit, columns, sort, cache)
+    })
+    .await;
+    let mut rows = units.into_iter().zip(details).collect_vec();
+    match sort {
+        Some(sort) => rows
+            .sort_by(|(a, a_details), (b, b_details)| sort.compare((a, a_details), (b, b_details))),
+        None => rows.sort_by_key(|(unit, _)| {
+            let group = unit
+                .info
+                .template()
+                .unwrap_or_else(|| unit.info.name.clone());
+            (
+                unit.daemon,
+                group,
+                !unit.info.is_template(),
+                unit.info.name.clone(),
+            )
+        }),
+    }
+    rows
+}
@@ -0,0 +1,196 @@
+//! `--completions`, and the hidden `sys __complete` the scripts call for live values.
+//!
+//! The scripts are clap_complete's, which know every flag, with a hook in front that first
+//! runs `sys __complete INDEX WORDS...`. That prints the unit names for patterns and `-F`,
+//! and the types and states units have right now for `-t`, `-s` and `-x`, from the managers
+//! the line so far asks for. Where it prints nothing the static completion takes over.
+use std::{
+    collections::BTreeSet,
+    io::{
+        self,
+        Write,
+    },
+};
+
+use clap::{
+    CommandFactory,
+    Parser,
+    ValueEnum,
+};
+use clap_complete::Shell;
+use sys::{
+    units_of,
+    Filter,
+    TypeOpt,
+};
+
+use crate::{
+    connections,
+    ArgSpec,
+    Cli,
+    Command,
+};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+const BASH_HOOK: &str = r#"_sys_live() {
+    local IFS=$'\n'
+    COMPREPLY=( $(sys __complete "${COMP_CWORD}" "${COMP_WORDS[@]}" 2>/dev/null) )
+    if [[ ${#COMPREPLY[@]} -eq 0 ]]; then
+        _sys "$@"
+    fi
+}
+
+complete -F _sys_live -o bashdefault -o default sys
+"#;
+
+const ZSH_HOOK: &str = r#"_sys_live() {
+    local -a values
+    values=( "${(@f)$(sys __complete $((CURRENT - 1)) "${words[@]}" 2>/dev/null)}" )
+    if [[ -n "${values[1]}" ]]; then
+        compadd -a values
+    else
+        _sys "$@"
+    fi
+}
+
+_sys_live "$@"
+"#;
+
+const FISH_HOOK: &str = r#"complete -c sys -f -a '(sys __complete (count (commandline -opc)) (commandline -opc) (commandline -ct) 2>/dev/null)'
+"#;
+
+pub fn print_script(shell: CompletionShell) {
+    let mut script = Vec::new();
+    let generator = match shell {
+        CompletionShell::Bash => Shell::Bash,
+        CompletionShell::Zsh => Shell::Zsh,
+        CompletionShell::Fish => Shell::Fish,
+    };
+    // without the hidden __complete, bash scripts name subcommands by their path joined by __
+    let mut command = ArgSpec::command().subcommands(
+        Cli::command()
+            .get_subcommands()
+            .filter(|command| !command.is_hide_set())
+            .cloned(),
+    );
+    clap_complete::generate(generator, &mut command, "sys", &mut script);
+    let script = String::from_utf8(script).expect("clap_complete wrote no utf-8");
+
+    // the hook takes over where the script registers itself
+    let script = match shell {
+        CompletionShell::Bash => match script.rfind("complete -F _sys ") {
+            Some(i) => format!("{}{BASH_HOOK}", &script[..i]),
+            None => script,
+        },
+        CompletionShell::Zsh => match script.rfind("_sys \"$@\"") {
+            Some(i) => format!("{}{ZSH_HOOK}", &script[..i]),
+            None => script,
+        },
+        CompletionShell::Fish => format!("{script}{FISH_HOOK}"),
+    };
+    print!("{script}");
+}
+
+enum Wanted {
+    Names,
+    Types,
+    States,
+}
+
+/// `sys __complete INDEX WORDS...`, the candidates for `WORDS[INDEX]` one per line
+pub async fn complete(args: &[String]) -> io::Result<()> {
+    let Some((index, words)) = args.split_first() else {
+        return Ok(());
+    };
+    let Ok(index) = index.parse::<usize>() else {
+        return Ok(());
+    };
+    let current = words.get(index).map_or("", String::as_str);
+    if current.starts_with('-') || index == 0 {
+        return Ok(());
+    }
+
+    let wanted = match words[index - 1].as_str() {
+        "-F" => Wanted::Names,
+        "-t" | "--type" => Wanted::Types,
+        "-s" | "--state" | "-x" | "--exclude" => Wanted::States,
+        option if takes_value(option) => return Ok(()),
+        _ => Wanted::Names,
+    };
+
+    let args = line_args(&words[..index.min(words.len())]);
+    let mut errors = Vec::new();
+    let filter = Filter {
+        skip_unloaded: args.no_unloaded,
+        ..Default::default()
+    };
+    let mut values = BTreeSet::new();
+    let conns = connections(&args, &mut errors).await;
+    let (all_units, _) = units_of(&conns, &filter).await;
+    for unit in all_units.into_values().flatten() {
+        let info = unit.info;
+        match wanted {
+            Wanted::Names => {
+                values.insert(info.name);
+            }
+            Wanted::Types => {
+                if !matches!(info.unit_type, TypeOpt::Other(_)) {
+                    values.insert(info.unit_type.to_string());
+                }
+            }
+            Wanted::States => {
+                // -s takes the names we don't know too
+                values.insert(info.loaded.to_string());
+                values.insert(info.active.to_string());
+                values.insert(info.status.to_string());
+            }
+        }
+    }
+
+    let mut stdout = io::stdout().lock();
+    for value in values.iter().filter(|value| value.starts_with(current)) {
+        writeln!(stdout, "{value}")?;
+    }
+    Ok(())
+}
+
+/// whether an option takes the next word as its value, like -n or --columns
+fn takes_value(option: &str) -> bool {
+    ArgSpec::command().get_arguments().any(|arg| {
+        let named = match option.strip_prefix("--") {
+            Some(long) => arg.get_long() == Some(long),
+            None => option
+                .strip_prefix('-')
+                .is_some_and(|short| arg.get_short().map(String::from).as_deref() == Some(short)),
+        };
+        named && arg.is_takes_value_set() && !arg.is_require_equals_set()
+    })
+}
+
+/// the line before the word to complete, for the daemon options on it
+fn line_args(words: &[String]) -> ArgSpec {
+    // a pattern so a line without one parses, or the value of an option at the end
+    for end in [words.len(), words.len().saturating_sub(1)] {
+        let line = words[..end].iter().map(String::as_str).chain(["."]);
+        let args = match Cli::try_parse_from(line) {
+            Ok(Cli {
+                command: Some(Command::Tui(args)),
+                ..
+            }) => args,
+            Ok(Cli {
+                command: Some(Command::Cat(cat)),
+                ..
+            }) => cat.args,
+            Ok(Cli { args, .. }) => args,
+            Err(_) => continue,
+        };
+        return args;
+    }
+    ArgSpec::parse_from(["sys", "."])
+}
@@ -0,0 +1,131 @@
+//! Getting a connection to each manager we want to talk to: the system one, ours, and
+//! other users', which as root means borrowing their uid to get onto their bus.
+use std::str::FromStr;
+
+use itertools::Itertools;
+use tracing::debug;
+use zbus::{
+    Connection,
+    ConnectionBuilder,
+};
+use zbus_systemd::zbus::Address;
+
+use crate::{
+    error::SysError,
+    logind::{
+        self,
+        Guess,
+    },
+    DaemonType,
+};
+
+/// the system bus, reusing our connection to the system daemon if we have one
+pub async fn system_connection(conns: &[(DaemonType, Connection)]) -> zbus::Result<Connection> {
+    match conns
+        .iter()
+        .find(|(daemon, _)| *daemon == DaemonType::System)
+    {
+        Some((_, conn)) => Ok(conn.clone()),
+        None => Connection::system().await,
+    }
+}
+
+/// a manager on some other bus, like a private one in tests
+pub async fn address_connection(address: &str) -> zbus::Result<Connection> {
+    ConnectionBuilder::address(Address::from_str(address)?)?
+        .build()
+        .await
+}
+
+/// the uid of the user whose daemon we want, via logind on the system bus
+pub async fn logind_uid(conns: &[(DaemonType, Connection)]) -> Result<u32, SysError> {
+    let system = system_connection(conns).await?;
+
+    match logind::guess_uid(&system).await? {
+        Guess::User(uid) => Ok(uid),
+        Guess::Ambiguous(sessions) => Err(SysError::Usage(format!(
+            "several users are logged in, pass --user=NAME or --uid UID for one of {}",
+            sessions
+                .iter()
+                .map(|s| format!("{} (uid {})", s.user, s.uid))
+                .join(", ")
+        ))),
+        Guess::Nobody => Err(SysError::NotFound(
+            "no user is logged in (try sudo or SUDO_UID)".to_string(),
+        )),
+    }
+}
+
+/// connect to a user's bus, as root we borrow their uid since the bus only lets them in
+pub async fn user_connection(uid: u32, address: &str) -> Result<Connection, SysError> {
+    let address = Address::from_str(address)?;
+    debug!("connecting to {}", address);
+
+    let root = users::get_current_uid() == 0;
+    if root {
+        users::switch::set_effective_uid(uid)
+            .map_err(|e| SysError::Permission(format!("could not become uid {uid}: {e}")))?;
+    }
+    let conn = ConnectionBuilder::address(address)?.build().await;
+    if root {
+        users::switch::set_effective_uid(0)
+            .map_err(|e| SysError::Permission(format!("could not restore euid: {e}")))?;
+    }
+    Ok(conn?)
+}
+
+/// the bus of the user with this uid, at the place systemd puts it
+pub async fn uid_connection(uid: u32) -> Result<Connection, SysError> {
+    match uid == users::get_current_uid() {
+        true => Ok(Connection::session().await?),
+        false => user_connection(uid, &format!("unix:path=/run/user/{uid}/bus")).await,
+    }
+}
+
+/// the daemon plain `--user` means: ours, or whoever we are sudo'd from
+pub async fn default_user_connection(
+    conns: &[(DaemonType, Connection)],
+) -> Result<(u32, Connection), SysError> {
+    if users::get_current_uid() != 0 {
+        // NOTE: i'm not sure what happens if there *is* a user session for root, like if you logged into a graphical session as root.
+        let conn = Connection::session().await?;
+        return Ok((users::get_current_uid(), conn));
+    }
+
+    let uid_res = std::env::var("SUDO_UID")
+        .map(|v| v.parse::<u32>().map_err(|_| SysError::parse("SUDO_UID", v)));
+    let (uid, address) = match uid_res {
+        Ok(uid) => match uid? {
+            0 => logind_address(conns).await?,
+            uid => {
+                // honor XDG_RUNTIME_DIR and DBUS_SESSION_BUS_ADDRESS
+                let address = std::env::var("DBUS_SESSION_BUS_ADDRESS").unwrap_or_else(|_| {
+                    let runtime_dir = std::env::var("XDG_RUNTIME_DIR")
+                        .ok()
+                        .unwrap_or_else(|| format!("/run/user/{}", uid));
+                    format!("unix:path={}/bus", runtime_dir)
+                });
+                (uid, address)
+            }
+        },
+        Err(_) => logind_address(conns).await?,
+    };
+
+    Ok((uid, user_connection(uid, &address).await?))
+}
+
+/// root's own environment would point at root's bus, so go by the uid
+async fn logind_address(conns: &[(DaemonType, Connection)]) -> Result<(u32, String), SysError> {
+    let uid = logind_uid(conns).await?;
+    Ok((uid, format!("unix:path=/run/user/{}/bus", uid)))
+}
+
+/// a user given by name or uid
+pub fn uid(user: &str) -> Result<u32, SysError> {
+    if let Ok(uid) = user.parse() {
+        return Ok(uid);
+    }
+    users::get_user_by_name(user)
+        .map(|user| user.uid())
+        .ok_or_else(|| SysError::NotFound(format!("no such user {user}")))
+}
@@ -0,0 +1,147 @@
+//! What can go wrong, and which exit code each kind of failure maps to.
+//!
+//! A failure that only concerns one unit is reported and the other units carry on, the
+//! first such error then decides the exit code. Exit codes are meant for scripts to rely on:
+//!
+//! | code | meaning                                                        |
+//! |------|----------------------------------------------------------------|
+//! | 0    | everything worked                                              |
+//! | 1    | a job did not succeed (failed, canceled, timeout, dependency)  |
+//! | 2    | bad command line                                               |
+//! | 3    | no unit matched, or a unit, unit file or user does not exist   |
+//! | 4    | permission denied, usually polkit saying no                    |
+//! | 5    | talking to D-Bus failed                                        |
+//! | 6    | systemd told us something we could not parse                   |
+//! | 7    | the output or the terminal could not be written                |
+use std::{
+    fmt::Display,
+    io::{
+        self,
+        ErrorKind,
+    },
+};
+
+pub const EXIT_JOB_FAILED: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_NOT_FOUND: i32 = 3;
+pub const EXIT_PERMISSION: i32 = 4;
+pub const EXIT_DBUS: i32 = 5;
+pub const EXIT_PARSE: i32 = 6;
+pub const EXIT_IO: i32 = 7;
+
+/// for `--help`, keep in sync with the table above
+pub const EXIT_STATUS_HELP: &str = "EXIT STATUS:
+    0  success
+    1  a job did not succeed
+    2  bad command line
+    3  no unit matched, or a unit, unit file or user does not exist
+    4  permission denied
+    5  D-Bus error
+    6  unexpected data from systemd
+    7  could not write the output or the terminal";
+
+#[derive(Debug)]
+pub enum SysError {
+    DBus(zbus::Error),
+    /// `what` we expected, the `value` we got instead
+    Parse {
+        what: &'static str,
+        value: String,
+    },
+    Permission(String),
+    NotFound(String),
+    /// the command line has to say more, like which user
+    Usage(String),
+    /// writing the output, a closed pipe is not one of these
+    Io(io::Error),
+}
+
+impl SysError {
+    pub fn parse(what: &'static str, value: impl Into<String>) -> Self {
+        SysError::Parse {
+            what,
+            value: value.into(),
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            SysError::DBus(_) => EXIT_DBUS,
+            SysError::Parse { .. } => EXIT_PARSE,
+            SysError::Permission(_) => EXIT_PERMISSION,
+            SysError::NotFound(_) => EXIT_NOT_FOUND,
+            SysError::Usage(_) => EXIT_USAGE,
+            SysError::Io(_) => EXIT_IO,
+        }
+    }
+}
+
+impl Display for SysError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SysError::DBus(e) => write!(f, "dbus: {e}"),
+            SysError::Parse { what, value } => write!(f, "invalid {what} {value:?}"),
+            SysError::Permission(e) => write!(f, "permission denied: {e}"),
+            SysError::NotFound(e) => write!(f, "not found: {e}"),
+            SysError::Usage(e) => write!(f, "{e}"),
+            SysError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for SysError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SysError::DBus(e) => Some(e),
+            SysError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// error names systemd and the bus use for the failures we tell apart
+/// https://github.com/systemd/systemd/blob/main/src/libsystemd/sd-bus/bus-common-errors.h
+const PERMISSION_ERRORS: [&str; 2] = [
+    "org.freedesktop.DBus.Error.AccessDenied",
+    "org.freedesktop.DBus.Error.InteractiveAuthorizationRequired",
+];
+const NOT_FOUND_ERRORS: [&str; 4] = [
+    "org.freedesktop.systemd1.NoSuchUnit",
+    "org.freedesktop.systemd1.NoSuchJob",
+    "org.freedesktop.DBus.Error.FileNotFound",
+    "org.freedesktop.DBus.Error.UnknownObject",
+];
+
+impl From<zbus::Error> for SysError {
+    fn from(e: zbus::Error) -> Self {
+        let described =
+            |description: &Option<String>| description.clone().unwrap_or_else(|| e.to_string());
+        match &e {
+            zbus::Error::MethodError(name, description, _) => {
+                if PERMISSION_ERRORS.contains(&name.as_str()) {
+                    return SysError::Permission(described(description));
+                }
+                if NOT_FOUND_ERRORS.contains(&name.as_str()) {
+                    return SysError::NotFound(described(description));
+                }
+            }
+            zbus::Error::FDO(fdo) => match fdo.as_ref() {
+                zbus::fdo::Error::AccessDenied(description)
+                | zbus::fdo::Error::InteractiveAuthorizationRequired(description) => {
+                    return SysError::Permission(description.clone())
+                }
+                zbus::fdo::Error::FileNotFound(description)
+                | zbus::fdo::Error::UnknownObject(description) => {
+                    return SysError::NotFound(description.clone())
+                }
+                _ => {}
+            },
+            // the bus socket of someone else's session
+            zbus::Error::InputOutput(io) if io.kind() == ErrorKind::PermissionDenied => {
+                return SysError::Permission(io.to_string())
+            }
+            _ => {}
+        }
+        SysError::DBus(e)
+    }
+}
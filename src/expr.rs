@@ -0,0 +1,262 @@
+//! `-w`: a small language for which units we want, beyond what `-s`, `-x` and `-t` can say.
+//!
+//! ```text
+//! expr  := or
+//! or    := and ('|' and)*
+//! and   := not ('&' not)*
+//! not   := '!' not | '(' expr ')' | term
+//! term  := key '=' value | word
+//! ```
+//!
+//! Keys are `load`, `active`, `sub` (or `status`), `type`, `file` (the unit file state),
+//! `daemon` (`system`, `user`, or a user's name or uid) and `name`, which takes a regex.
+//! A bare word is whatever it can be: `failed` is `active=failed | sub=failed`, `enabled`
+//! is `file=enabled` and `service` is `type=service`. Values can be quoted, so
+//! `name='^(a|b)\.'` works.
+//!
+//! Expressions are evaluated against a [`ListUnitsItem`], unit files nobody loaded yet as the
+//! [`ListUnitsItem::unloaded`] they are listed as.
+use std::{
+    fmt::Display,
+    str::FromStr,
+};
+
+use regex::Regex;
+
+use crate::{
+    state::{
+        ActiveState,
+        LoadState,
+        State,
+        SubState,
+    },
+    DaemonType,
+    ListUnitsItem,
+    TypeOpt,
+    UnitFileStatus,
+};
+
+#[derive(Debug, Clone)]
+pub enum DaemonMatch {
+    System,
+    /// any user's daemon
+    User,
+    Uid(u32),
+}
+
+#[derive(Debug, Clone)]
+pub enum Term {
+    Load(LoadState),
+    Active(ActiveState),
+    Sub(&'static str),
+    Type(TypeOpt),
+    File(UnitFileStatus),
+    Daemon(DaemonMatch),
+    Name(Regex),
+}
+
+impl Term {
+    fn eval(&self, unit: &ListUnitsItem, daemon: DaemonType) -> bool {
+        match self {
+            Term::Load(state) => unit.loaded == State::Known(*state),
+            Term::Active(state) => unit.active == State::Known(*state),
+            Term::Sub(state) => unit.status.to_string() == *state,
+            Term::Type(unit_type) => unit.unit_type == *unit_type,
+            Term::File(state) => unit.unit_file_state == Some(*state),
+            Term::Daemon(DaemonMatch::System) => daemon == DaemonType::System,
+            Term::Daemon(DaemonMatch::User) => matches!(daemon, DaemonType::User(_)),
+            Term::Daemon(DaemonMatch::Uid(uid)) => daemon == DaemonType::User(*uid),
+            Term::Name(re) => re.is_match(&unit.name),
+        }
+    }
+
+    fn parse(key: &str, value: &str) -> Result<Self, String> {
+        let invalid = |what: &str| format!("unknown {what} {value:?}");
+        Ok(match key {
+            "load" => Term::Load(value.parse().map_err(|_| invalid("load state"))?),
+            "active" => Term::Active(value.parse().map_err(|_| invalid("active state"))?),
+            "sub" | "status" => Term::Sub(
+                SubState::names()
+                    .find(|name| *name == value)
+                    .ok_or_else(|| invalid("sub state"))?,
+            ),
+            "type" => match <TypeOpt as FromStr>::from_str(value) {
+                Ok(TypeOpt::Other(_)) | Err(_) => return Err(invalid("unit type")),
+                Ok(unit_type) => Term::Type(unit_type),
+            },
+            "file" | "unit-file" => {
+                Term::File(value.parse().map_err(|_| invalid("unit file state"))?)
+            }
+            "daemon" => Term::Daemon(match value {
+                "system" => DaemonMatch::System,
+                "user" => DaemonMatch::User,
+                user => DaemonMatch::Uid(match user.parse() {
+                    Ok(uid) => uid,
+                    Err(_) => users::get_user_by_name(user)
+                        .ok_or_else(|| invalid("user"))?
+                        .uid(),
+                }),
+            }),
+            "name" => Term::Name(Regex::new(value).map_err(|e| e.to_string())?),
+            key => return Err(format!("unknown key {key:?}")),
+        })
+    }
+
+    /// every meaning a bare word has
+    fn bare(word: &str) -> Result<Expr, String> {
+        let meanings = ["load", "active", "sub", "type", "file"]
+            .into_iter()
+            .filter_map(|key| Term::parse(key, word).ok())
+            .chain(match word {
+                "system" => Some(Term::Daemon(DaemonMatch::System)),
+                "user" => Some(Term::Daemon(DaemonMatch::User)),
+                _ => None,
+            })
+            .map(Expr::Term);
+        meanings
+            .reduce(|a, b| Expr::Or(Box::new(a), Box::new(b)))
+            .ok_or_else(|| format!("{word:?} is no state, type or unit file state"))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Term(Term),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn eval(&self, unit: &ListUnitsItem, daemon: DaemonType) -> bool {
+        match self {
+            Expr::Term(term) => term.eval(unit, daemon),
+            Expr::Not(expr) => !expr.eval(unit, daemon),
+            Expr::And(a, b) => a.eval(unit, daemon) && b.eval(unit, daemon),
+            Expr::Or(a, b) => a.eval(unit, daemon) || b.eval(unit, daemon),
+        }
+    }
+
+    pub fn and(self, other: Expr) -> Expr {
+        Expr::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: Expr) -> Expr {
+        Expr::Or(Box::new(self), Box::new(other))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Word(String),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+            Token::And => write!(f, "'&'"),
+            Token::Or => write!(f, "'|'"),
+            Token::Not => write!(f, "'!'"),
+            Token::Word(word) => write!(f, "{word:?}"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '&' => Token::And,
+            '|' => Token::Or,
+            '!' => Token::Not,
+            c => {
+                let mut word = String::new();
+                let mut next = Some(c);
+                while let Some(c) = next {
+                    match c {
+                        '\'' | '"' => loop {
+                            match chars.next() {
+                                Some(end) if end == c => break,
+                                Some(c) => word.push(c),
+                                None => return Err(format!("unterminated {c}")),
+                            }
+                        },
+                        c => word.push(c),
+                    }
+                    next = chars.next_if(|c| !c.is_whitespace() && !"()&|!".contains(*c));
+                }
+                Token::Word(word)
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+}
+
+impl Parser {
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.tokens.next_if_eq(&Token::Or).is_some() {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.tokens.next_if_eq(&Token::And).is_some() {
+            expr = expr.and(self.not()?);
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        match self.tokens.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.not()?))),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.tokens.next() {
+                    Some(Token::Close) => Ok(expr),
+                    Some(token) => Err(format!("expected ')' but got {token}")),
+                    None => Err("missing ')'".to_string()),
+                }
+            }
+            Some(Token::Word(word)) => match word.split_once('=') {
+                Some((key, value)) => Ok(Expr::Term(Term::parse(key, value)?)),
+                None => Term::bare(&word),
+            },
+            Some(token) => Err(format!("unexpected {token}")),
+            None => Err("unexpected end".to_string()),
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?.into_iter().peekable(),
+        };
+        let expr = parser.or()?;
+        match parser.tokens.next() {
+            Some(token) => Err(format!("unexpected {token}")),
+            None => Ok(expr),
+        }
+    }
+}
@@ -0,0 +1,422 @@
+//! `--follow`: a live stream of what systemd does to the matched units, no matter who asked
+//! for it. We only react to D-Bus signals and new journal entries, so a `systemctl restart`
+//! from another terminal shows up the same as one issued by sys.
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    path::PathBuf,
+    time::Duration,
+};
+
+use futures::{
+    stream::{
+        self,
+        LocalBoxStream,
+        SelectAll,
+    },
+    StreamExt,
+};
+use tracing::warn;
+use zbus::{
+    fdo::PropertiesProxy,
+    zvariant::{
+        OwnedObjectPath,
+        Value,
+    },
+    Connection,
+};
+use zbus_systemd::systemd1::{
+    JobProxy,
+    ManagerProxy,
+    UnitProxy,
+};
+
+use crate::{
+    journal::JournalFollower,
+    properties::UNIT_INTERFACE,
+    status::{
+        colorize_status,
+        format_journal_entry,
+        journal_units,
+    },
+    DaemonType,
+    ListUnitsItem,
+    Unit,
+};
+
+enum Event {
+    State {
+        daemon: DaemonType,
+        name: String,
+        active: Option<String>,
+        sub: Option<String>,
+    },
+    UnitNew {
+        daemon: DaemonType,
+        name: String,
+        path: OwnedObjectPath,
+    },
+    UnitRemoved {
+        daemon: DaemonType,
+        name: String,
+    },
+    JobNew {
+        daemon: DaemonType,
+        id: u32,
+        job: OwnedObjectPath,
+        unit: String,
+    },
+    JobRemoved {
+        daemon: DaemonType,
+        id: u32,
+        unit: String,
+        result: String,
+    },
+    Tick,
+}
+
+async fn state_events<'c>(
+    conn: &'c Connection,
+    daemon: DaemonType,
+    name: String,
+    path: OwnedObjectPath,
+) -> zbus::Result<LocalBoxStream<'c, Event>> {
+    let properties = PropertiesProxy::builder(conn)
+        .destination("org.freedesktop.systemd1")?
+        .path(path)?
+        .build()
+        .await?;
+
+    let stream = properties.receive_properties_changed().await?;
+    Ok(stream
+        .filter_map(move |signal| {
+            let name = name.clone();
+            async move {
+                let args = signal.args().ok()?;
+                if args.interface_name().as_str() != UNIT_INTERFACE {
+                    return None;
+                }
+                let get = |key: &str| match args.changed_properties().get(key) {
+                    Some(Value::Str(s)) => Some(s.as_str().to_string()),
+                    _ => None,
+                };
+                let active = get("ActiveState");
+                let sub = get("SubState");
+                if active.is_none() && sub.is_none() {
+                    return None;
+                }
+                Some(Event::State {
+                    daemon,
+                    name,
+                    active,
+                    sub,
+                })
+            }
+        })
+        .boxed_local())
+}
+
+async fn manager_events<'c>(
+    manager: &ManagerProxy<'c>,
+    daemon: DaemonType,
+) -> zbus::Result<Vec<LocalBoxStream<'c, Event>>> {
+    let unit_new = manager
+        .receive_unit_new()
+        .await?
+        .filter_map(move |signal| async move {
+            let args = signal.args().ok()?;
+            Some(Event::UnitNew {
+                daemon,
+                name: args.id().to_string(),
+                path: args.unit().clone(),
+            })
+        })
+        .boxed_local();
+
+    let unit_removed = manager
+        .receive_unit_removed()
+        .await?
+        .filter_map(move |signal| async move {
+            let args = signal.args().ok()?;
+            Some(Event::UnitRemoved {
+                daemon,
+                name: args.id().to_string(),
+            })
+        })
+        .boxed_local();
+
+    let job_new = manager
+        .receive_job_new()
+        .await?
+        .filter_map(move |signal| async move {
+            let args = signal.args().ok()?;
+            Some(Event::JobNew {
+                daemon,
+                id: *args.id(),
+                job: args.job().clone(),
+                unit: args.unit().to_string(),
+            })
+        })
+        .boxed_local();
+
+    let job_removed = manager
+        .receive_job_removed()
+        .await?
+        .filter_map(move |signal| async move {
+            let args = signal.args().ok()?;
+            Some(Event::JobRemoved {
+                daemon,
+                id: *args.id(),
+                unit: args.unit().to_string(),
+                result: args.result().to_string(),
+            })
+        })
+        .boxed_local();
+
+    Ok(vec![unit_new, unit_removed, job_new, job_removed])
+}
+
+fn print_event(daemon: DaemonType, unit: &Unit, message: impl std::fmt::Display) {
+    let time = chrono::Local::now().format("%b %d %H:%M:%S");
+    println!(
+        "{} {} {}{}: {}",
+        console::style(time).dim(),
+        console::style(format!("{daemon}:")).dim(),
+        unit.info.base_name,
+        unit.info.unit_type.color_str(true),
+        message
+    );
+}
+
+fn state_str(active: &str, sub: &str) -> String {
+    format!(
+        "{} ({})",
+        colorize_status(active).apply_to(active),
+        colorize_status(sub).apply_to(sub)
+    )
+}
+
+/// Stream state transitions, jobs and journal entries for the matched units until ctrl-c.
+///
+/// Units that appear later are picked up if `matches` accepts them.
+pub async fn follow<'a>(
+    all_units: &BTreeMap<DaemonType, Vec<Unit<'a>>>,
+    conns: &'a [(DaemonType, Connection)],
+    matches: impl Fn(&ListUnitsItem) -> bool,
+    journal_dirs: &[PathBuf],
+) {
+    let mut watched: HashMap<(DaemonType, String), Unit<'a>> = all_units
+        .iter()
+        .flat_map(|(daemon, units)| {
+            units
+                .iter()
+                .map(|u| ((*daemon, u.info.name.clone()), u.clone()))
+        })
+        .collect();
+    let mut states: HashMap<(DaemonType, String), (String, String)> = watched
+        .iter()
+        .map(|(key, unit)| {
+            (
+                key.clone(),
+                (unit.info.active.to_string(), unit.info.status.to_string()),
+            )
+        })
+        .collect();
+
+    let mut events: SelectAll<LocalBoxStream<'a, Event>> = SelectAll::new();
+    let mut managers = HashMap::new();
+    for (daemon, conn) in conns {
+        let manager = match ManagerProxy::new(conn).await {
+            Ok(manager) => manager,
+            Err(e) => {
+                warn!("could not follow {daemon} daemon: {e}");
+                continue;
+            }
+        };
+        match manager_events(&manager, *daemon).await {
+            Ok(streams) => events.extend(streams),
+            Err(e) => warn!("could not follow {daemon} daemon jobs: {e}"),
+        }
+        managers.insert(*daemon, (conn, manager));
+    }
+
+    for ((daemon, name), unit) in watched.iter() {
+        let Some((conn, _)) = managers.get(daemon) else {
+            continue;
+        };
+        // not loaded, UnitNew tells us when it is
+        let Some(proxy) = &unit.proxy else {
+            continue;
+        };
+        let path = proxy.inner().path().to_owned().into();
+        match state_events(conn, *daemon, name.clone(), path).await {
+            Ok(stream) => events.push(stream),
+            Err(e) => warn!("could not follow {name}: {e}"),
+        }
+    }
+
+    let mut journal = JournalFollower::new(journal_dirs);
+    for path in journal.unreadable() {
+        warn!("could not read journal {}", path.display());
+    }
+    events.push(
+        stream::unfold(
+            tokio::time::interval(Duration::from_millis(250)),
+            |mut interval| async move {
+                interval.tick().await;
+                Some((Event::Tick, interval))
+            },
+        )
+        .boxed_local(),
+    );
+
+    println!(
+        "{}",
+        console::style(format!("following {} units, ctrl-c to stop", watched.len())).dim()
+    );
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    loop {
+        let event = tokio::select! {
+            _ = &mut ctrl_c => break,
+            event = events.next() => match event {
+                Some(event) => event,
+                None => break,
+            },
+        };
+
+        match event {
+            Event::State {
+                daemon,
+                name,
+                active,
+                sub,
+            } => {
+                let key = (daemon, name);
+                let Some(unit) = watched.get(&key) else {
+                    continue;
+                };
+                let (old_active, old_sub) = states.entry(key.clone()).or_default();
+                let new_active = active.unwrap_or_else(|| old_active.clone());
+                let new_sub = sub.unwrap_or_else(|| old_sub.clone());
+                if new_active == *old_active && new_sub == *old_sub {
+                    continue;
+                }
+                print_event(
+                    daemon,
+                    unit,
+                    format!(
+                        "{} → {}",
+                        state_str(old_active, old_sub),
+                        state_str(&new_active, &new_sub)
+                    ),
+                );
+                *old_active = new_active;
+                *old_sub = new_sub;
+            }
+            Event::UnitNew { daemon, name, path } => {
+                let key = (daemon, name);
+                // its state changes are followed already
+                let watching = watched.get(&key);
+                if let Some(unit) = watching.filter(|unit| unit.proxy.is_some()) {
+                    print_event(daemon, unit, console::style("loaded").dim());
+                    continue;
+                }
+                let loaded = watching.is_some();
+                let Some((conn, manager)) = managers.get(&daemon) else {
+                    continue;
+                };
+                let proxy = async { UnitProxy::builder(conn).path(path.clone())?.build().await };
+                let Ok(proxy) = proxy.await else {
+                    continue;
+                };
+                let info = match ListUnitsItem::from_proxy(&proxy).await {
+                    Ok(info) => info,
+                    Err(e) => {
+                        warn!("could not follow {}: {e}", key.1);
+                        continue;
+                    }
+                };
+                // a unit we watch that was only a unit file so far, or a new one
+                if !loaded && !matches(&info) {
+                    continue;
+                }
+
+                let unit = Unit {
+                    info,
+                    daemon,
+                    manager: manager.clone(),
+                    proxy: Some(proxy),
+                };
+                match loaded {
+                    true => print_event(daemon, &unit, console::style("loaded").dim()),
+                    false => print_event(daemon, &unit, console::style("new unit").bold()),
+                }
+                match state_events(conn, daemon, key.1.clone(), path).await {
+                    Ok(stream) => events.push(stream),
+                    Err(e) => warn!("could not follow {}: {e}", key.1),
+                }
+                states.insert(
+                    key.clone(),
+                    (unit.info.active.to_string(), unit.info.status.to_string()),
+                );
+                watched.insert(key, unit);
+            }
+            Event::UnitRemoved { daemon, name } => {
+                if let Some(unit) = watched.get(&(daemon, name)) {
+                    print_event(daemon, unit, console::style("unloaded").dim());
+                }
+            }
+            Event::JobNew {
+                daemon,
+                id,
+                job,
+                unit,
+            } => {
+                let Some(watched_unit) = watched.get(&(daemon, unit)) else {
+                    continue;
+                };
+                // the job may already be gone by the time we ask
+                let conn = watched_unit.manager.inner().connection();
+                let job = async { JobProxy::builder(conn).path(job)?.build().await };
+                let job_type = match job.await {
+                    Ok(job) => job.job_type().await.unwrap_or_default(),
+                    Err(_) => String::new(),
+                };
+                print_event(
+                    daemon,
+                    watched_unit,
+                    format!("job {id} {} queued", console::style(job_type).bold()),
+                );
+            }
+            Event::JobRemoved {
+                daemon,
+                id,
+                unit,
+                result,
+            } => {
+                let Some(watched_unit) = watched.get(&(daemon, unit)) else {
+                    continue;
+                };
+                let result = match result.as_str() {
+                    "done" | "skipped" => console::style(result).green(),
+                    _ => console::style(result).red(),
+                };
+                print_event(daemon, watched_unit, format!("job {id} {result}"));
+            }
+            Event::Tick => {
+                for entry in journal.poll() {
+                    let unit = journal_units(&entry)
+                        .find_map(|(daemon, name)| watched.get(&(daemon, name.to_string())));
+                    if let Some(unit) = unit {
+                        println!("{}", format_journal_entry(&entry, unit));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,599 @@
+//! reader for journald's on disk format, so we don't need libsystemd or journalctl
+//! https://systemd.io/JOURNAL_FILE_FORMAT/
+use std::{
+    cell::RefCell,
+    collections::{
+        BTreeMap,
+        HashMap,
+        HashSet,
+    },
+    fs,
+    hash::Hasher,
+    io::{
+        self,
+        Read,
+    },
+    os::unix::fs::FileExt,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+const SIGNATURE: &[u8; 8] = b"LPKSHHRH";
+
+const HEADER_INCOMPATIBLE_COMPRESSED_XZ: u32 = 1 << 0;
+const HEADER_INCOMPATIBLE_COMPRESSED_LZ4: u32 = 1 << 1;
+const HEADER_INCOMPATIBLE_KEYED_HASH: u32 = 1 << 2;
+const HEADER_INCOMPATIBLE_COMPRESSED_ZSTD: u32 = 1 << 3;
+const HEADER_INCOMPATIBLE_COMPACT: u32 = 1 << 4;
+const HEADER_INCOMPATIBLE_SUPPORTED: u32 = HEADER_INCOMPATIBLE_COMPRESSED_XZ
+    | HEADER_INCOMPATIBLE_COMPRESSED_LZ4
+    | HEADER_INCOMPATIBLE_KEYED_HASH
+    | HEADER_INCOMPATIBLE_COMPRESSED_ZSTD
+    | HEADER_INCOMPATIBLE_COMPACT;
+
+const OBJECT_DATA: u8 = 1;
+const OBJECT_ENTRY: u8 = 3;
+const OBJECT_ENTRY_ARRAY: u8 = 6;
+
+const OBJECT_COMPRESSED_XZ: u8 = 1 << 0;
+const OBJECT_COMPRESSED_LZ4: u8 = 1 << 1;
+const OBJECT_COMPRESSED_ZSTD: u8 = 1 << 2;
+
+const OBJECT_HEADER_SIZE: u64 = 16;
+/// head and tail offset of a hash table bucket
+const HASH_ITEM_SIZE: u64 = 16;
+
+/// decompressed data objects kept per file
+const DATA_CACHE: usize = 4096;
+
+/// default locations journald writes to, persistent and volatile
+pub const DEFAULT_DIRS: [&str; 2] = ["/var/log/journal", "/run/log/journal"];
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// CLOCK_REALTIME in usec
+    pub realtime: u64,
+    /// counts up with every entry written under the same `seqnum_id`, so with it an entry
+    /// is told apart from any other, like the cursor of sd-journal
+    pub seqnum: u64,
+    pub seqnum_id: [u8; 16],
+    pub fields: BTreeMap<String, String>,
+}
+
+impl Entry {
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str)
+    }
+
+    pub fn message(&self) -> &str {
+        self.field("MESSAGE").unwrap_or_default()
+    }
+
+    /// syslog priority, 0 (emerg) to 7 (debug)
+    pub fn priority(&self) -> u8 {
+        self.field("PRIORITY")
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(6)
+    }
+}
+
+/// A journal file read with positioned reads.
+///
+/// Not mapped: journald truncates and rotates files under their readers, and touching a
+/// mapped page past the end of the file raises SIGBUS, while a read just comes up short.
+pub struct JournalFile {
+    path: PathBuf,
+    file: fs::File,
+    /// end of the tail object, anything after is unused or still being written
+    end: u64,
+    compact: bool,
+    keyed_hash: bool,
+    header_size: u64,
+    file_id: [u8; 16],
+    seqnum_id: [u8; 16],
+    n_objects: u64,
+    data_hash_table_offset: u64,
+    data_hash_table_size: u64,
+    cache: RefCell<HashMap<u64, Vec<u8>>>,
+}
+
+fn align8(n: u64) -> u64 {
+    (n + 7) & !7
+}
+
+impl JournalFile {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = JournalFile {
+            path: path.to_path_buf(),
+            file: fs::File::open(path)?,
+            end: 0,
+            compact: false,
+            keyed_hash: false,
+            header_size: 0,
+            file_id: [0; 16],
+            seqnum_id: [0; 16],
+            n_objects: 0,
+            data_hash_table_offset: 0,
+            data_hash_table_size: 0,
+            cache: Default::default(),
+        };
+        file.read_header()?;
+        Ok(file)
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        self.end = self.file.metadata()?.len();
+        if self.end < 208 || &self.read(0, 8)?[..] != SIGNATURE {
+            return Err(invalid(format!(
+                "{} is not a journal file",
+                self.path.display()
+            )));
+        }
+        self.file_id = self.read(24, 16)?.try_into().unwrap();
+        self.seqnum_id = self.read(72, 16)?.try_into().unwrap();
+
+        let incompatible = self.le32(12)?;
+        if incompatible & !HEADER_INCOMPATIBLE_SUPPORTED != 0 {
+            return Err(invalid(format!(
+                "{} uses unsupported journal features {incompatible:#x}",
+                self.path.display()
+            )));
+        }
+        self.compact = incompatible & HEADER_INCOMPATIBLE_COMPACT != 0;
+        self.keyed_hash = incompatible & HEADER_INCOMPATIBLE_KEYED_HASH != 0;
+        self.header_size = self.le64(88)?;
+        self.data_hash_table_offset = self.le64(104)?;
+        self.data_hash_table_size = self.le64(112)?;
+        self.n_objects = self.le64(144)?;
+
+        // older files may not have the tail offset, keep everything and stop at the first hole
+        let tail = self.le64(136)?;
+        if tail != 0 {
+            // the tail object may not be written completely yet, or the file was cut short
+            self.end = match self.object_header(tail) {
+                Ok((_, _, size)) if tail + align8(size) <= self.end => tail + align8(size),
+                _ => self.end.min(tail),
+            };
+        }
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// journald renames the active file when rotating, this stays the same
+    pub fn file_id(&self) -> [u8; 16] {
+        self.file_id
+    }
+
+    /// offset after the last complete object, for [`JournalFile::entries_from`]
+    pub fn end_offset(&self) -> u64 {
+        self.end
+    }
+
+    pub fn set_path(&mut self, path: PathBuf) {
+        self.path = path;
+    }
+
+    /// Pick up objects journald appended since the file was opened.
+    ///
+    /// Objects are never moved and the fields we read are never rewritten once the object
+    /// is linked in, so only the header and the size of the file need reading again.
+    pub fn refresh(&mut self) -> io::Result<()> {
+        // vacuumed files are gone from the directory, though we still have them open
+        fs::metadata(&self.path)?;
+        self.read_header()
+    }
+
+    /// len bytes at offset, an error past the end of what was written, or if the file was
+    /// truncated since
+    fn read(&self, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        offset
+            .checked_add(len)
+            .filter(|end| *end <= self.end)
+            .ok_or_else(|| invalid(format!("{} truncated at {offset}", self.path.display())))?;
+        let mut buf = vec![0; len as usize];
+        self.file.read_exact_at(&mut buf, offset)?;
+        Ok(buf)
+    }
+
+    fn le32(&self, offset: u64) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(
+            self.read(offset, 4)?.try_into().unwrap(),
+        ))
+    }
+
+    fn le64(&self, offset: u64) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(
+            self.read(offset, 8)?.try_into().unwrap(),
+        ))
+    }
+
+    /// (type, flags, size) of the object at offset
+    fn object_header(&self, offset: u64) -> io::Result<(u8, u8, u64)> {
+        let header = self.read(offset, OBJECT_HEADER_SIZE)?;
+        let size = u64::from_le_bytes(header[8..16].try_into().unwrap());
+        Ok((header[0], header[1], size))
+    }
+
+    /// Entries with any of the `FIELD=value` pairs, in the order they were written.
+    ///
+    /// Each pair is looked up in the data hash table and only the entries its entry arrays
+    /// point to are read. In a file cut short, the entries that could be found come first,
+    /// then the error.
+    pub fn entries_matching(
+        &self,
+        matches: &[String],
+    ) -> impl Iterator<Item = io::Result<Entry>> + '_ {
+        let mut offsets = Vec::new();
+        let mut error = None;
+        for pair in matches {
+            let found = match self.find_data(pair.as_bytes()) {
+                Ok(Some(data)) => self.data_entries(data, &mut offsets),
+                Ok(None) => Ok(()),
+                Err(e) => Err(e),
+            };
+            // the other pairs may still be readable
+            if let Err(e) = found {
+                error.get_or_insert(e);
+            }
+        }
+        // entries are appended, so their offsets are in the order they were written
+        offsets.sort_unstable();
+        offsets.dedup();
+        offsets
+            .into_iter()
+            .map(|offset| self.entry(offset))
+            .chain(error.map(Err))
+    }
+
+    /// Entries of objects starting at or after offset, for tailing a file.
+    ///
+    /// Objects are laid out back to back (8 byte aligned) after the header, so this scans
+    /// what was appended since the last call rather than walking the entry array chain,
+    /// which also copes with the tail still being written to.
+    pub fn entries_from(&self, offset: u64) -> impl Iterator<Item = io::Result<Entry>> + '_ {
+        let mut offset = offset.max(self.header_size);
+        let len = self.end_offset();
+
+        std::iter::from_fn(move || {
+            while offset < len {
+                let (typ, _flags, size) = match self.object_header(offset) {
+                    Ok(v) => v,
+                    Err(_) => return None,
+                };
+                if typ == 0 || size < OBJECT_HEADER_SIZE {
+                    return None;
+                }
+                let current = offset;
+                offset = current.saturating_add(align8(size));
+
+                if typ == OBJECT_ENTRY {
+                    return Some(self.entry(current));
+                }
+            }
+            None
+        })
+    }
+
+    /// the hash of a data payload, as the data hash table is keyed by
+    fn hash(&self, payload: &[u8]) -> u64 {
+        match self.keyed_hash {
+            true => {
+                let key = |half: &[u8]| u64::from_le_bytes(half.try_into().unwrap());
+                #[allow(deprecated)] // SipHasher is SipHash-2-4, as systemd uses
+                let mut hasher = std::hash::SipHasher::new_with_keys(
+                    key(&self.file_id[..8]),
+                    key(&self.file_id[8..]),
+                );
+                hasher.write(payload);
+                hasher.finish()
+            }
+            false => jenkins_hash64(payload),
+        }
+    }
+
+    /// offset of the data object with the payload, through the data hash table
+    fn find_data(&self, payload: &[u8]) -> io::Result<Option<u64>> {
+        let buckets = self.data_hash_table_size / HASH_ITEM_SIZE;
+        if buckets == 0 {
+            return Ok(None);
+        }
+        let hash = self.hash(payload);
+        let mut offset =
+            self.le64(self.data_hash_table_offset + hash % buckets * HASH_ITEM_SIZE)?;
+        // a chain can't be longer than there are objects, unless the file is corrupt
+        let mut left = self.n_objects;
+        while offset != 0 && left > 0 {
+            if self.le64(offset + 16)? == hash && self.data_payload(offset)? == payload {
+                return Ok(Some(offset));
+            }
+            offset = self.le64(offset + 24)?;
+            left -= 1;
+        }
+        Ok(None)
+    }
+
+    /// offsets of the entries a data object is in, the first is in the object itself, the
+    /// rest in its entry array chain
+    fn data_entries(&self, data: u64, offsets: &mut Vec<u64>) -> io::Result<()> {
+        let n_entries = self.le64(data + 56)?;
+        if n_entries == 0 {
+            return Ok(());
+        }
+        offsets.push(self.le64(data + 40)?);
+        self.entry_array(self.le64(data + 48)?, n_entries - 1, offsets)
+    }
+
+    /// up to n entry offsets from an entry array chain
+    fn entry_array(&self, mut array: u64, mut n: u64, offsets: &mut Vec<u64>) -> io::Result<()> {
+        let item_size = if self.compact { 4 } else { 8 };
+        while array != 0 && n > 0 {
+            let (typ, _flags, size) = self.object_header(array)?;
+            if typ != OBJECT_ENTRY_ARRAY {
+                return Err(invalid(format!(
+                    "{}: expected entry array at {array}, found type {typ}",
+                    self.path.display()
+                )));
+            }
+            let count = (size.saturating_sub(24) / item_size).min(n);
+            let items = self.read(array + 24, count * item_size)?;
+            for item in items.chunks(item_size as usize) {
+                let offset = match self.compact {
+                    true => u32::from_le_bytes(item.try_into().unwrap()) as u64,
+                    false => u64::from_le_bytes(item.try_into().unwrap()),
+                };
+                // the rest of the array is not used yet
+                if offset == 0 {
+                    return Ok(());
+                }
+                offsets.push(offset);
+            }
+            n -= count;
+            array = self.le64(array + 16)?;
+        }
+        Ok(())
+    }
+
+    fn entry(&self, offset: u64) -> io::Result<Entry> {
+        let (typ, _flags, size) = self.object_header(offset)?;
+        if typ != OBJECT_ENTRY {
+            return Err(invalid(format!(
+                "{}: expected entry at {offset}, found type {typ}",
+                self.path.display()
+            )));
+        }
+        let object = self.read(offset, size)?;
+        let seqnum = u64::from_le_bytes(object[16..24].try_into().unwrap());
+        let realtime = u64::from_le_bytes(object[24..32].try_into().unwrap());
+
+        let item_size = if self.compact { 4 } else { 16 };
+        let mut fields = BTreeMap::new();
+        for item in object.get(64..).unwrap_or_default().chunks_exact(item_size) {
+            let data_offset = match self.compact {
+                true => u32::from_le_bytes(item.try_into().unwrap()) as u64,
+                false => u64::from_le_bytes(item[..8].try_into().unwrap()),
+            };
+            let payload = self.data_payload(data_offset)?;
+            let payload = String::from_utf8_lossy(&payload);
+            if let Some((name, value)) = payload.split_once('=') {
+                fields.insert(name.to_string(), value.to_string());
+            }
+        }
+
+        Ok(Entry {
+            realtime,
+            seqnum,
+            seqnum_id: self.seqnum_id,
+            fields,
+        })
+    }
+
+    /// the payload of a data object, decompressed
+    ///
+    /// Entries of a unit share most of their fields, so these are kept for the next one.
+    fn data_payload(&self, offset: u64) -> io::Result<Vec<u8>> {
+        if let Some(payload) = self.cache.borrow().get(&offset) {
+            return Ok(payload.clone());
+        }
+
+        let (typ, flags, size) = self.object_header(offset)?;
+        if typ != OBJECT_DATA {
+            return Err(invalid(format!(
+                "{}: expected data object at {offset}, found type {typ}",
+                self.path.display()
+            )));
+        }
+        let payload_offset = if self.compact { 72 } else { 64 };
+        let payload = self.read(offset + payload_offset, size.saturating_sub(payload_offset))?;
+        let payload = decompress(flags, payload)?;
+
+        let mut cache = self.cache.borrow_mut();
+        if cache.len() >= DATA_CACHE {
+            cache.clear();
+        }
+        cache.insert(offset, payload.clone());
+        Ok(payload)
+    }
+}
+
+fn decompress(flags: u8, payload: Vec<u8>) -> io::Result<Vec<u8>> {
+    if flags & OBJECT_COMPRESSED_ZSTD != 0 {
+        let mut out = Vec::new();
+        ruzstd::StreamingDecoder::new(&payload[..])
+            .map_err(|e| invalid(e.to_string()))?
+            .read_to_end(&mut out)?;
+        Ok(out)
+    } else if flags & OBJECT_COMPRESSED_LZ4 != 0 {
+        // journald prefixes the lz4 block with the uncompressed size
+        let (len, block) = payload.split_at(8.min(payload.len()));
+        let len = u64::from_le_bytes(len.try_into().map_err(|_| invalid("bad lz4 payload"))?);
+        lz4_flex::block::decompress(block, len as usize).map_err(|e| invalid(e.to_string()))
+    } else if flags & OBJECT_COMPRESSED_XZ != 0 {
+        let mut out = Vec::new();
+        lzma_rs::xz_decompress(&mut &payload[..], &mut out).map_err(|e| invalid(e.to_string()))?;
+        Ok(out)
+    } else {
+        Ok(payload)
+    }
+}
+
+/// Bob Jenkins' lookup3 hashlittle2, what files from before keyed hashes use
+fn jenkins_hash64(data: &[u8]) -> u64 {
+    let word = |bytes: &[u8]| u32::from_le_bytes(bytes.try_into().unwrap());
+
+    let init = 0xdeadbeef_u32.wrapping_add(data.len() as u32);
+    let (mut a, mut b, mut c) = (init, init, init);
+    let mut rest = data;
+    while rest.len() > 12 {
+        a = a.wrapping_add(word(&rest[0..4]));
+        b = b.wrapping_add(word(&rest[4..8]));
+        c = c.wrapping_add(word(&rest[8..12]));
+
+        a = a.wrapping_sub(c) ^ c.rotate_left(4);
+        c = c.wrapping_add(b);
+        b = b.wrapping_sub(a) ^ a.rotate_left(6);
+        a = a.wrapping_add(c);
+        c = c.wrapping_sub(b) ^ b.rotate_left(8);
+        b = b.wrapping_add(a);
+        a = a.wrapping_sub(c) ^ c.rotate_left(16);
+        c = c.wrapping_add(b);
+        b = b.wrapping_sub(a) ^ a.rotate_left(19);
+        a = a.wrapping_add(c);
+        c = c.wrapping_sub(b) ^ b.rotate_left(4);
+        b = b.wrapping_add(a);
+
+        rest = &rest[12..];
+    }
+
+    if !rest.is_empty() {
+        let mut last = [0; 12];
+        last[..rest.len()].copy_from_slice(rest);
+        a = a.wrapping_add(word(&last[0..4]));
+        b = b.wrapping_add(word(&last[4..8]));
+        c = c.wrapping_add(word(&last[8..12]));
+
+        c = (c ^ b).wrapping_sub(b.rotate_left(14));
+        a = (a ^ c).wrapping_sub(c.rotate_left(11));
+        b = (b ^ a).wrapping_sub(a.rotate_left(25));
+        c = (c ^ b).wrapping_sub(b.rotate_left(16));
+        a = (a ^ c).wrapping_sub(c.rotate_left(4));
+        b = (b ^ a).wrapping_sub(a.rotate_left(14));
+        c = (c ^ b).wrapping_sub(b.rotate_left(24));
+    }
+    (c as u64) << 32 | b as u64
+}
+
+/// All journal files below the given directories.
+///
+/// Journald keeps files in a subdirectory per machine id; both `system.journal` and
+/// `user-UID.journal` are included, along with their rotated `@` archives. Files
+/// journald marked as corrupt (`.journal~`) are skipped.
+pub fn journal_files(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = dirs.to_vec();
+    while let Some(dir) = pending.pop() {
+        let Ok(read_dir) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in read_dir.filter_map(Result::ok) {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|e| e == "journal") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// Tails every journal file in a set of directories.
+///
+/// Files are tracked by file id rather than path, so a rotated file is not read twice.
+pub struct JournalFollower {
+    dirs: Vec<PathBuf>,
+    files: HashMap<[u8; 16], (JournalFile, u64)>,
+    paths: HashMap<PathBuf, [u8; 16]>,
+    unreadable: HashSet<PathBuf>,
+}
+
+impl JournalFollower {
+    /// start following from the current end of every existing file
+    pub fn new(dirs: &[PathBuf]) -> Self {
+        let mut follower = JournalFollower {
+            dirs: dirs.to_vec(),
+            files: Default::default(),
+            paths: Default::default(),
+            unreadable: Default::default(),
+        };
+        for path in journal_files(dirs) {
+            if let Ok(file) = JournalFile::open(&path) {
+                let end = file.end_offset();
+                follower.paths.insert(path, file.file_id());
+                follower.files.insert(file.file_id(), (file, end));
+            } else {
+                follower.unreadable.insert(path);
+            }
+        }
+        follower
+    }
+
+    /// paths journald could not be read from, so the caller can warn once
+    pub fn unreadable(&self) -> impl Iterator<Item = &Path> {
+        self.unreadable.iter().map(PathBuf::as_path)
+    }
+
+    /// entries written since the last poll, in chronological order
+    pub fn poll(&mut self) -> Vec<Entry> {
+        for path in journal_files(&self.dirs) {
+            if self.paths.contains_key(&path) || self.unreadable.contains(&path) {
+                continue;
+            }
+            let Ok(file) = JournalFile::open(&path) else {
+                self.unreadable.insert(path);
+                continue;
+            };
+            let id = file.file_id();
+            self.paths.insert(path.clone(), id);
+            match self.files.get_mut(&id) {
+                // rotated, keep reading where we were
+                Some((known, _)) => known.set_path(path),
+                // created after we started, so everything in it is new
+                None => {
+                    let start = file.header_size;
+                    self.files.insert(id, (file, start));
+                }
+            }
+        }
+
+        let mut entries = Vec::new();
+        let mut gone = Vec::new();
+        for (id, (file, offset)) in self.files.iter_mut() {
+            match file.refresh() {
+                Ok(()) => {}
+                // vacuumed
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    gone.push(*id);
+                    continue;
+                }
+                Err(_) => continue,
+            }
+            entries.extend(file.entries_from(*offset).filter_map(Result::ok));
+            *offset = file.end_offset();
+        }
+        self.files.retain(|id, _| !gone.contains(id));
+        self.paths.retain(|_, id| !gone.contains(id));
+
+        entries.sort_by_key(|entry| entry.realtime);
+        entries
+    }
+}
@@ -0,0 +1,50 @@
+//! remember, the d in systemd stands for demented
+//!
+//! The workings of the `sys` command, for use from other tools:
+//! [`connect`] to the managers, wrap each in a [`Daemon`], ask it for the [`Unit`]s passing a
+//! [`Filter`], then start, stop or enable them.
+pub mod actions;
+pub mod cat;
+pub mod columns;
+pub mod connect;
+pub mod error;
+pub mod expr;
+pub mod follow;
+pub mod journal;
+pub mod logind;
+pub mod output;
+pub mod properties;
+pub mod query;
+pub mod state;
+pub mod status;
+pub mod table;
+pub mod tui;
+pub mod unit;
+
+pub use error::SysError;
+pub use query::{
+    load_units,
+    units_of,
+    Daemon,
+    Filter,
+};
+pub use state::{
+    State,
+    StateFilter,
+    StateOpt,
+};
+pub use unit::{
+    split_instance,
+    split_unit_name,
+    unit_file_name,
+    user_name,
+    DaemonType,
+    JobResult,
+    ListUnitsItem,
+    TypeOpt,
+    Unit,
+    UnitAction,
+    UnitFile,
+    UnitFileChange,
+    UnitFileStatus,
+};
@@ -0,0 +1,124 @@
+//! Working out which users' managers to talk to, from logind on the system bus.
+use itertools::Itertools;
+use zbus::Connection;
+use zbus_systemd::login1::{
+    ManagerProxy,
+    SessionProxy,
+    UserProxy,
+};
+
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub id: String,
+    pub uid: u32,
+    pub user: String,
+    pub active: bool,
+    /// attached to a seat, as opposed to ssh and the like
+    pub seat: bool,
+    pub graphical: bool,
+}
+
+pub async fn sessions(system: &Connection) -> zbus::Result<Vec<Session>> {
+    let manager = ManagerProxy::new(system).await?;
+    let mut sessions = Vec::new();
+    for (id, uid, user, seat, path) in manager.list_sessions().await? {
+        let session = SessionProxy::builder(system).path(path)?.build().await?;
+        // greeters and the like are not someone's session
+        if session.class().await.unwrap_or_default() != "user" {
+            continue;
+        }
+        let typ: String = session
+            .inner()
+            .get_property("Type")
+            .await
+            .unwrap_or_default();
+
+        sessions.push(Session {
+            id,
+            uid,
+            user,
+            active: session.active().await.unwrap_or_default(),
+            seat: !seat.is_empty(),
+            graphical: typ == "x11" || typ == "wayland" || typ == "mir",
+        });
+    }
+    Ok(sessions)
+}
+
+/// Everyone with a running user manager, logged in or lingering.
+pub async fn users(system: &Connection) -> zbus::Result<Vec<u32>> {
+    let manager = ManagerProxy::new(system).await?;
+    let mut uids = Vec::new();
+    for (uid, _, path) in manager.list_users().await? {
+        let user = UserProxy::builder(system).path(path)?.build().await?;
+        // the manager is on its way out
+        if user.state().await.unwrap_or_default() == "closing" {
+            continue;
+        }
+        uids.push(uid);
+    }
+    Ok(uids)
+}
+
+/// what [`guess_uid`] made of the sessions
+#[derive(Debug, Clone)]
+pub enum Guess {
+    User(u32),
+    /// several users equally likely, one session each, and nobody picked one of them
+    Ambiguous(Vec<Session>),
+    Nobody,
+}
+
+/// Owner of the active graphical seat session, falling back to less certain guesses.
+/// When several users are equally likely we ask, if there is a terminal to ask on.
+pub async fn guess_uid(system: &Connection) -> zbus::Result<Guess> {
+    let sessions = sessions(system).await?;
+    let sessions = sessions.iter().filter(|s| s.uid != 0).collect_vec();
+
+    let tiers: [&dyn Fn(&Session) -> bool; 3] = [
+        &|s| s.active && s.seat && s.graphical,
+        &|s| s.active && s.seat,
+        &|s| s.active,
+    ];
+
+    for tier in tiers {
+        let candidates = sessions
+            .iter()
+            .filter(|s| tier(s))
+            .unique_by(|s| s.uid)
+            .collect_vec();
+
+        match candidates.len() {
+            0 => continue,
+            1 => return Ok(Guess::User(candidates[0].uid)),
+            _ => {
+                return Ok(match pick_user(&candidates) {
+                    Some(uid) => Guess::User(uid),
+                    None => {
+                        Guess::Ambiguous(candidates.into_iter().map(|s| (*s).clone()).collect())
+                    }
+                })
+            }
+        }
+    }
+    Ok(Guess::Nobody)
+}
+
+fn pick_user(candidates: &[&&Session]) -> Option<u32> {
+    if !console::Term::stderr().is_term() {
+        return None;
+    }
+    let items = candidates
+        .iter()
+        .map(|s| format!("{} (uid {}, session {})", s.user, s.uid, s.id))
+        .collect_vec();
+
+    dialoguer::Select::new()
+        .with_prompt("Which user's daemon?")
+        .items(&items)
+        .default(0)
+        .interact_opt()
+        .ok()
+        .flatten()
+        .map(|i| candidates[i].uid)
+}
@@ -1,51 +1,120 @@
-//! remember, the d in systemd stands for demented
+//! the command line, see lib.rs for the rest
+mod completion;
+mod progress;
+
 use std::{
-    any::Any,
     collections::{
         BTreeMap,
-        HashSet,
+        HashMap,
     },
     fmt::Display,
+    io::IsTerminal,
     path::PathBuf,
     process::exit,
-    str::FromStr,
 };
 
 use clap::{
     ArgAction,
+    Args,
     Parser,
+    Subcommand,
     ValueEnum,
 };
-use console::{
-    Style,
-    StyledObject,
-};
-use futures::future::join_all;
+use completion::CompletionShell;
 use itertools::Itertools;
 use regex::Regex;
-use tracing::{
-    info,
-    span,
-    Level,
-};
-use zbus::{
-    zvariant::OwnedObjectPath,
-    Connection,
-    ConnectionBuilder,
-};
-use zbus_systemd::{
-    systemd1::{
-        ManagerProxy,
-        UnitProxy,
+use sys::{
+    actions::{
+        confirm,
+        run_jobs,
+        UnitFileActions,
+        PICK_OVER,
+    },
+    columns::{
+        self,
+        Column,
+        SortKey,
+        DEFAULT_COLUMNS,
+    },
+    connect,
+    error,
+    expr::{
+        Expr,
+        Term,
     },
-    zbus::Address,
+    follow,
+    journal::{
+        self,
+        JournalFollower,
+    },
+    logind,
+    output::{
+        ActionRecord,
+        Output,
+        OutputFormat,
+        UnitRecord,
+    },
+    properties::PropertyCache,
+    status::{
+        print_journal,
+        read_journal,
+    },
+    table::{
+        table,
+        TableOptions,
+    },
+    tui,
+    units_of,
+    user_name,
+    Daemon,
+    DaemonType,
+    Filter,
+    JobResult,
+    ListUnitsItem,
+    StateFilter,
+    StateOpt,
+    SysError,
+    TypeOpt,
+    Unit,
+    UnitAction,
+    UnitFileStatus,
 };
+use tracing_subscriber::layer::SubscriberExt;
+use zbus::Connection;
 
 #[derive(Parser, Debug)]
 #[clap(version, arg_required_else_help(true), about, long_about = None)]
+#[clap(after_help = error::EXIT_STATUS_HELP)]
+// after an option a word named like a subcommand is a pattern, like in `sys -a cat`
+#[clap(args_conflicts_with_subcommands = true, disable_help_subcommand = true)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    #[clap(flatten)]
+    args: ArgSpec,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// a live view of the units that S, K, R, 1 and 0 act on like the options do
+    Tui(ArgSpec),
+    /// print the unit files of the units
+    Cat(CatSpec),
+    /// the live values for the completion scripts, see completion.rs
+    #[clap(name = "__complete", hide = true)]
+    #[clap(trailing_var_arg = true, disable_help_flag = true)]
+    Complete {
+        /// INDEX WORDS...
+        #[clap(allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+}
+
+#[derive(Parser, Debug)]
 /*#[clap(group(
     ArgGroup::new("daemon-args")
-        .args(&["daemon", "user_only", "system_only"]),
+        .args(&["daemon", "users", "system_only"]),
 ))]*/
 struct ArgSpec {
     #[clap(long = "debug-colors")]
@@ -63,6 +132,12 @@ struct ArgSpec {
     #[clap(short, long)]
     force: bool,
 
+    /// pick the units that get the actions from a list, done without asking on a terminal
+    /// once more than 5 units match
+    #[clap(short, long)]
+    interactive: bool,
+
+    /// enable, disable, mask and unmask only until reboot, in /run
     #[clap(long)]
     runtime: bool,
 
@@ -78,6 +153,13 @@ struct ArgSpec {
     #[clap(short = '0', long)]
     disable: bool,
 
+    /// link unit files to /dev/null so nothing can start them
+    #[clap(long, conflicts_with_all = &["unmask", "enable"])]
+    mask: bool,
+    /// remove masks, before -1 enables
+    #[clap(long)]
+    unmask: bool,
+
     #[clap(short = 'S', long)]
     start: bool,
     #[clap(short = 'K', long)]
@@ -89,6 +171,15 @@ struct ArgSpec {
     status: bool,
     #[clap(short = 'L', long, alias = "logs")]
     journal: bool,
+    /// only show the last N journal entries
+    #[clap(short = 'n', long)]
+    lines: Option<usize>,
+    /// keep running and print state changes and journal entries as they happen
+    #[clap(long, alias = "watch")]
+    follow: bool,
+    /// read journal files from here instead of the journald defaults
+    #[clap(long = "journal-dir", action = ArgAction::Append)]
+    journal_dirs: Vec<PathBuf>,
 
     #[clap(short = 'r', long = "daemon-reload")]
     daemon_reload: bool,
@@ -104,8 +195,27 @@ struct ArgSpec {
 
     #[clap(long = "system")]
     system_only: bool,
-    #[clap(long = "user")]
-    user_only: bool,
+    /// only the user daemon, or with =NAME those users' daemons (name or uid, comma separated)
+    #[clap(
+        long = "user",
+        value_name = "USER",
+        min_values = 0,
+        require_equals = true,
+        value_delimiter = ',',
+        action = ArgAction::Append
+    )]
+    users: Option<Vec<String>>,
+    /// the daemon of the user with this uid, like --user=NAME
+    #[clap(long = "uid", action = ArgAction::Append)]
+    uids: Vec<u32>,
+    /// the daemons of every user logind knows about
+    #[clap(long = "all-users")]
+    all_users: bool,
+    /// talk to a manager on this D-Bus address instead, the system one unless --user is given.
+    /// With {uid} in it, it is where the bus of each user named with --user=NAME, --uid or
+    /// --all-users is
+    #[clap(long = "bus-address", env = "SYS_BUS_ADDRESS")]
+    bus_address: Option<String>,
 
     #[clap(short = 'd', value_enum, default_value_t = DaemonOpt::Either)]
     daemon: DaemonOpt,
@@ -116,13 +226,68 @@ struct ArgSpec {
     #[clap(short = 'q', long = "quiet")]
     quiet: bool,
 
-    #[clap(short = 's', long = "state", value_enum)]
-    status_filter: Vec<StatusOpt>,
+    #[clap(short = 's', long = "state", value_parser = StateOpt::value_parser())]
+    status_filter: Vec<StateOpt>,
+
+    #[clap(short = 'x', long = "exclude", value_parser = StateOpt::value_parser())]
+    status_filterx: Vec<StateOpt>,
 
-    //#[clap(short = 'a', long = "and", value_enum)]
-    //status_filtera : Vec<StatusOpt>,
-    #[clap(short = 'x', long = "exclude", value_enum)]
-    status_filterx: Vec<StatusOpt>,
+    /// only units this expression holds for, like 'active=failed & type=service & !enabled',
+    /// see expr.rs. Given more than once, all have to hold
+    #[clap(short = 'w', long = "where", value_parser, action = ArgAction::Append)]
+    exprs: Vec<Expr>,
+
+    /// list unit files systemd has not loaded too, from the unit file alone. The default
+    #[clap(short = 'a', long = "all", overrides_with = "no_unloaded")]
+    all: bool,
+    /// leave out unit files systemd has not loaded
+    #[clap(long = "no-unloaded", overrides_with = "all")]
+    no_unloaded: bool,
+
+    /// only units enabled, also just until reboot. Several of these match any of them
+    #[clap(long)]
+    enabled: bool,
+    /// only units that are disabled
+    #[clap(long)]
+    disabled: bool,
+    /// only units that can't be enabled and are pulled in by others instead
+    #[clap(long = "static")]
+    static_: bool,
+    /// only masked units, also just until reboot
+    #[clap(long)]
+    masked: bool,
+
+    /// only instances of these templates, like getty, getty@ or getty@.service, comma separated
+    #[clap(long = "template", value_delimiter = ',', action = ArgAction::Append)]
+    templates: Vec<String>,
+    /// only these instances, comma separated. Templates named with --template or a pattern
+    /// like getty@ are made into them, so `sys -S getty@ --instance tty2` starts
+    /// getty@tty2.service
+    #[clap(long = "instance", value_delimiter = ',', action = ArgAction::Append)]
+    instances: Vec<String>,
+
+    /// table columns instead of the ones -v and -t pick, comma separated
+    #[clap(long = "columns", value_enum, value_delimiter = ',', action = ArgAction::Append)]
+    columns: Vec<Column>,
+    /// order units by this instead of by daemon and name
+    #[clap(long = "sort", value_enum)]
+    sort: Option<SortKey>,
+
+    /// print the units and action results as json or ndjson, or only the units as csv,
+    /// instead of a table
+    #[clap(
+        short = 'o',
+        long = "output",
+        value_enum,
+        default_value_t = OutputFormat::Table,
+        conflicts_with_all = &["status", "journal", "follow"]
+    )]
+    output: OutputFormat,
+
+    /// print a completion script for this shell, one that completes unit names and states
+    /// too by asking the managers
+    #[clap(long, value_enum, value_name = "SHELL")]
+    completions: Option<CompletionShell>,
 }
 
 #[derive(Debug, ValueEnum, Clone)]
@@ -134,412 +299,353 @@ enum DaemonOpt {
     Either,
 }
 
-#[derive(
-    Debug, ValueEnum, Clone, strum::EnumString, strum::Display, PartialEq, Eq, PartialOrd, Ord,
-)]
-#[strum(serialize_all = "lowercase")]
-#[strum(ascii_case_insensitive)]
-enum TypeOpt {
-    #[clap(name = ".")]
-    _Any,
-
-    Socket,
-    #[clap(alias = "s")]
-    Service,
-    Network,
-    Device,
-    Timer,
-    Target,
-    Slice,
-    Scope,
-    Mount,
-    Swap,
-    Path,
+/// report an error that ends the run, and exit with its code
+fn fail(context: impl Display, e: SysError) -> ! {
+    eprintln!("ERROR: {context}: {e}");
+    exit(e.exit_code())
+}
+
+/// report an error that only affects some units and carry on with the rest
+fn report(errors: &mut Vec<SysError>, context: impl Display, e: SysError) {
+    eprintln!("ERROR: {context}: {e}");
+    errors.push(e);
 }
 
-impl TypeOpt {
-    fn color_str(&self, with_dot: bool) -> StyledObject<String> {
-        let unit_type_str = console::style(format!("{}{}", if with_dot { "." } else { "" }, self));
-        match self {
-            TypeOpt::Socket => unit_type_str.yellow(),
-            TypeOpt::Service => unit_type_str.white().bold(),
-            TypeOpt::Network => unit_type_str.red(),
-            TypeOpt::Device => unit_type_str.blue(),
-            TypeOpt::Timer => unit_type_str.green(),
-            TypeOpt::Target => unit_type_str.magenta().bright(),
-            TypeOpt::Slice => unit_type_str.yellow().dim(),
-            TypeOpt::Scope => unit_type_str.dim(),
-            TypeOpt::Mount => unit_type_str.magenta().dim(),
-            TypeOpt::Swap => unit_type_str.cyan().dim(),
-            TypeOpt::Path => unit_type_str.blue().dim(),
-            _ => panic!(),
+/// how writing the output went, a closed pipe like `sys -o json . | head` ends sys as if it
+/// was done
+fn written(res: std::io::Result<()>, errors: &mut Vec<SysError>) {
+    match res {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {
+            exit(errors.first().map_or(0, SysError::exit_code))
         }
+        Err(e) => report(errors, "writing output", SysError::Io(e)),
     }
+}
 
-    fn variants() -> [Self; 11] {
-        [
-            TypeOpt::Socket,
-            TypeOpt::Service,
-            TypeOpt::Network,
-            TypeOpt::Device,
-            TypeOpt::Timer,
-            TypeOpt::Target,
-            TypeOpt::Slice,
-            TypeOpt::Scope,
-            TypeOpt::Mount,
-            TypeOpt::Swap,
-            TypeOpt::Path,
-        ]
+/// LoadUnit what so far is only a unit file, the units that fail are reported and dropped
+async fn load_units(
+    all_units: &mut BTreeMap<DaemonType, Vec<Unit<'_>>>,
+    errors: &mut Vec<SysError>,
+) {
+    for (name, e) in sys::load_units(all_units).await {
+        report(errors, format!("loading {name}"), e);
     }
 }
 
-#[derive(
-    Debug, ValueEnum, Clone, Copy, strum::EnumString, strum::Display, PartialEq, Eq, PartialOrd, Ord,
-)]
-#[strum(serialize_all = "kebab-case")]
-#[strum(ascii_case_insensitive)]
-enum StatusOpt {
-    Loaded,
-    NotFound,
-    BadSetting,
-    Masked,
-
-    Active,
-    Inactive,
-
-    Failed,
-    Dead,
-    Running,
-    Plugged,
-    Mounted,
-    Waiting,
-    Exited,
-    Listening,
-    StatusActive,
-}
+/// the users named with --user=NAME, --uid and --all-users, empty if none were
+async fn target_uids(args: &ArgSpec, conns: &[(DaemonType, Connection)]) -> Vec<u32> {
+    let mut uids = args.uids.clone();
 
-#[derive(Debug, Clone, Copy, strum::EnumString, strum::Display, PartialEq, Eq, PartialOrd, Ord)]
-#[strum(serialize_all = "kebab-case")]
-#[strum(ascii_case_insensitive)]
-enum UnitFileStatus {
-    Linked,
-    LinkedRuntime,
-    Transient,
-    Masked,
-    Generated,
-    EnabledRuntime,
-    Enabled,
-    Disabled,
-    Alias,
-    Static,
-}
+    for name in args.users.iter().flatten() {
+        uids.push(connect::uid(name).unwrap_or_else(|e| fail("--user", e)));
+    }
 
-#[derive(Debug, Clone)]
-struct UnitFile {
-    path: PathBuf,
-    name: String,
-    typ: TypeOpt,
-    status: UnitFileStatus,
-}
-impl UnitFile {
-    fn new((path, status): (String, String)) -> Self {
-        let status = UnitFileStatus::from_str(&status)
-            .unwrap_or_else(|e| panic!("{e}\nbad unit file status {status}"));
-        let path = PathBuf::from_str(&path).unwrap();
-        let name: String = path.file_name().unwrap().to_str().unwrap().to_string();
-        let typ: TypeOpt = FromStr::from_str(path.extension().unwrap().to_str().unwrap()).unwrap();
-
-        UnitFile {
-            status,
-            path,
-            name,
-            typ,
-        }
+    if args.all_users {
+        let users = async { logind::users(&connect::system_connection(conns).await?).await }
+            .await
+            .unwrap_or_else(|e| fail("could not list users from logind", e.into()));
+        uids.extend(users);
     }
-}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-enum StatusOptType {
-    Loaded,
-    Active,
-    Status,
+    uids.into_iter().unique().collect()
 }
 
-impl StatusOpt {
-    fn get_type(&self) -> StatusOptType {
-        match self {
-            StatusOpt::Active | StatusOpt::Inactive => StatusOptType::Active,
-            StatusOpt::NotFound | StatusOpt::Loaded | StatusOpt::BadSetting => {
-                StatusOptType::Loaded
+/// the managers to talk to, as --system, --user, -d and friends say
+async fn connections(args: &ArgSpec, errors: &mut Vec<SysError>) -> Vec<(DaemonType, Connection)> {
+    let mut system = false;
+    let mut user = false;
+    if args.system_only {
+        system = true;
+    } else if args.users.is_some() || !args.uids.is_empty() || args.all_users {
+        user = true;
+    } else {
+        match args.daemon {
+            DaemonOpt::User => user = true,
+            DaemonOpt::System => system = true,
+            DaemonOpt::Either => {
+                user = true;
+                system = true;
             }
-            _ => StatusOptType::Status,
         }
     }
-}
-
-#[derive(Debug, Clone, Default)]
-struct StatusFields<T> {
-    pub loaded: T,
-    pub active: T,
-    pub status: T,
-}
-
-type StatusFilter = StatusFields<Vec<StatusOpt>>;
-impl StatusFilter {
-    fn filter_includes(&self, unit: &ListUnitsItem) -> bool {
-        //match by any
-        (self.loaded.is_empty() && self.active.is_empty() && self.status.is_empty())
-            || (self.loaded.contains(&unit.loaded)
-                || self.active.contains(&unit.active)
-                || self.status.contains(&unit.status))
-    }
 
-    /*
-    fn filter_and(&self, unit : &ListUnitsItem) -> bool{
-        //match by all
-        (self.loaded.is_empty() && self.active.is_empty() && self.status.is_empty()) ||
-        (
-            self.loaded.contains(&unit.loaded) &&
-            self.active.contains(&unit.active) &&
-            self.status.contains(&unit.status)
-        )
+    let mut conns: Vec<(DaemonType, Connection)> = Vec::new();
+    // the users' buses, left for below
+    let user_address = args
+        .bus_address
+        .as_ref()
+        .filter(|address| address.contains("{uid}"));
+    if let Some(address) = args.bus_address.as_ref().filter(|_| user_address.is_none()) {
+        let daemon = match system {
+            true => DaemonType::System,
+            false => DaemonType::User(users::get_current_uid()),
+        };
+        let conn = connect::address_connection(address)
+            .await
+            .unwrap_or_else(|e| fail(format!("could not connect to {address}"), e.into()));
+        conns.push((daemon, conn));
+        (system, user) = (false, false);
     }
-    */
-
-    fn filter_excludes(&self, unit: &ListUnitsItem) -> bool {
-        //match by none
-        (self.loaded.is_empty() && self.active.is_empty() && self.status.is_empty())
-            || ((!self.loaded.contains(&unit.loaded))
-                && (!self.active.contains(&unit.active))
-                && (!self.status.contains(&unit.status)))
+    if system {
+        let conn = Connection::system()
+            .await
+            .unwrap_or_else(|e| fail("could not connect to dbus system session", e.into()));
+        conns.push((DaemonType::System, conn));
     }
-}
-
-fn split_status_list(l: &[StatusOpt]) -> StatusFilter {
-    let mut ret = StatusFields::<Vec<StatusOpt>>::default();
-
-    for state in l {
-        if *state == StatusOpt::StatusActive {
-            ret.status.push(StatusOpt::Active)
-        } else {
-            match state.get_type() {
-                StatusOptType::Active => ret.active.push(*state),
-                StatusOptType::Loaded => ret.loaded.push(*state),
-                StatusOptType::Status => ret.status.push(*state),
+    if user {
+        let mut uids = target_uids(args, &conns).await;
+        if uids.is_empty() && user_address.is_some() {
+            uids.push(users::get_current_uid());
+        }
+        if uids.is_empty() {
+            let (uid, conn) = connect::default_user_connection(&conns)
+                .await
+                .unwrap_or_else(|e| fail("could not connect to dbus user session", e));
+            conns.push((DaemonType::User(uid), conn));
+        }
+        for uid in uids {
+            let conn = match user_address {
+                Some(address) => {
+                    connect::address_connection(&address.replace("{uid}", &uid.to_string()))
+                        .await
+                        .map_err(SysError::from)
+                }
+                None => connect::uid_connection(uid).await,
+            };
+            match conn {
+                Ok(conn) => conns.push((DaemonType::User(uid), conn)),
+                Err(e) => report(
+                    errors,
+                    format!(
+                        "could not connect to dbus user session of {} (uid:{uid})",
+                        user_name(uid)
+                    ),
+                    e,
+                ),
             }
         }
+        if !conns
+            .iter()
+            .any(|(daemon, _)| *daemon != DaemonType::System)
+        {
+            exit(errors.first().map_or(error::EXIT_DBUS, SysError::exit_code));
+        }
     }
-    ret
+    conns
 }
 
-fn colorize_status(text: &str) -> Style {
-    let style = Style::new();
-    match text {
-        "loaded" => style.dim(),
-        "not-found" => style.yellow(),
-        "active" => style,
-        "actives" => style,
-        "inactive" => style.dim(),
-        "failed" => style.red(),
-        "dead" => style.yellow(),
-        "running" => style.green(),
-        "plugged" => style,
-        "mounted" => style,
-        "waiting" => style.green().dim(),
-        "exited" => style.dim(),
-        "listening" => style.green().dim(),
-        _ => style,
+/// the units the filter options ask for
+fn filter(args: &ArgSpec) -> Filter {
+    let unit_file_states: [(bool, &[UnitFileStatus]); 4] = [
+        (
+            args.enabled,
+            &[UnitFileStatus::Enabled, UnitFileStatus::EnabledRuntime],
+        ),
+        (args.disabled, &[UnitFileStatus::Disabled]),
+        (args.static_, &[UnitFileStatus::Static]),
+        (
+            args.masked,
+            &[UnitFileStatus::Masked, UnitFileStatus::MaskedRuntime],
+        ),
+    ];
+    // the same as -w 'file=enabled | file=enabled-runtime | ...'
+    let unit_file_expr = unit_file_states
+        .into_iter()
+        .filter(|(wanted, _)| *wanted)
+        .flat_map(|(_, states)| states)
+        .map(|state| Expr::Term(Term::File(*state)))
+        .reduce(Expr::or);
+
+    Filter {
+        patterns: args
+            .patterns
+            .iter()
+            .cloned()
+            .chain(args.fixed_strings.iter().map(|s| Filter::fixed_string(s)))
+            .collect(),
+        any: args.multi,
+        types: match args.types.contains(&TypeOpt::_Any) {
+            true => TypeOpt::variants().to_vec(),
+            false => args.types.clone(),
+        },
+        include: StateFilter::new(&args.status_filter),
+        exclude: StateFilter::new(&args.status_filterx),
+        expr: args
+            .exprs
+            .iter()
+            .cloned()
+            .chain(unit_file_expr)
+            .reduce(Expr::and),
+        skip_unloaded: args.no_unloaded,
+        templates: args.templates.clone(),
+        instances: args.instances.clone(),
     }
 }
 
-#[derive(Debug, Clone)]
-struct ListUnitsItem {
-    name: String,      //0
-    desc: String,      //1
-    loaded: StatusOpt, //2
-    active: StatusOpt, //3
-    status: StatusOpt, //4
-    other_name: String,
-    path: OwnedObjectPath,
-    idk: u32,
-    idk2: String,
-    idk3: OwnedObjectPath,
-    unit_type: TypeOpt,
-    base_name: String,
+/// what is typed into the filter bar of `sys tui`, the options of the command line
+fn parse_line<'w>(words: impl IntoIterator<Item = &'w str>) -> Result<ArgSpec, clap::Error> {
+    ArgSpec::try_parse_from(std::iter::once("sys").chain(words))
 }
 
-lazy_static::lazy_static! {
-    static ref TYPE_REGEX : Regex = Regex::new(r"(.*)\.([^.]*)$").unwrap();
-}
-
-impl ListUnitsItem {
-    async fn from_proxy(proxy: &UnitProxy<'_>) -> Self {
-        let name = proxy.names().await.unwrap().first().unwrap().clone();
-        let m = TYPE_REGEX
-            .captures(&name)
-            .unwrap_or_else(|| panic!("error extracting unit type {}", name));
-        let base_name = m.get(1).unwrap().as_str().to_string();
-        let unit_type = m.get(2).unwrap().as_str();
-
-        let unit_type: TypeOpt = unit_type
-            .try_into()
-            .unwrap_or_else(|_| panic!("invalid unit type {}", unit_type));
-
-        Self {
-            name,
-            desc: proxy.description().await.unwrap().clone(),
-            loaded: {
-                let state = proxy.load_state().await.unwrap();
-                state.parse().unwrap_or_else(|e| panic!("{e} {state}"))
-            },
-            active: {
-                let state = proxy.active_state().await.unwrap();
-                state.parse().unwrap_or_else(|e| panic!("{e} {state}"))
-            },
-            status: {
-                let state = proxy.sub_state().await.unwrap();
-                state.parse().unwrap_or_else(|e| panic!("{e} {state}"))
-            },
-            other_name: Default::default(),
-            path: Default::default(), //XXX
-            idk: Default::default(),
-            idk2: Default::default(),
-            idk3: Default::default(),
-            unit_type,
-            base_name,
-        }
+/// `sys tui [OPTIONS] [PATTERNS]...`, see tui.rs
+async fn run_tui(args: ArgSpec) {
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        eprintln!("ERROR: sys tui needs a terminal");
+        exit(error::EXIT_USAGE);
     }
-}
 
-impl
-    From<(
-        String,
-        String,
-        String,
-        String,
-        String,
-        String,
-        OwnedObjectPath,
-        u32,
-        String,
-        OwnedObjectPath,
-    )> for ListUnitsItem
-{
-    fn from(
-        t: (
-            String,
-            String,
-            String,
-            String,
-            String,
-            String,
-            OwnedObjectPath,
-            u32,
-            String,
-            OwnedObjectPath,
-        ),
-    ) -> Self {
-        let m = TYPE_REGEX
-            .captures(&t.0)
-            .unwrap_or_else(|| panic!("error extracting unit type {}", t.0));
-
-        let base_name = m.get(1).unwrap().as_str().to_string();
-        let unit_type = m.get(2).unwrap().as_str();
-        let unit_type: TypeOpt = unit_type
-            .try_into()
-            .unwrap_or_else(|_| panic!("invalid unit type {}", unit_type));
-
-        let loaded: StatusOpt =
-            t.2.as_str()
-                .try_into()
-                .ok()
-                .filter(|v: &StatusOpt| (v.get_type() == StatusOptType::Loaded))
-                .unwrap_or_else(|| panic!("invalid unit loaded state {}", t.2));
-
-        let active: StatusOpt =
-            t.3.as_str()
-                .try_into()
-                .ok()
-                .filter(|v: &StatusOpt| {
-                    v.get_type() == StatusOptType::Active || *v == StatusOpt::Failed
-                })
-                .unwrap_or_else(|| panic!("invalid unit active state {}", t.3));
-
-        let status: StatusOpt =
-            t.4.as_str()
-                .try_into()
-                .ok()
-                .filter(|v: &StatusOpt| {
-                    v.get_type() == StatusOptType::Status || *v == StatusOpt::Active
-                })
-                .unwrap_or_else(|| panic!("invalid unit status {}", t.4));
-
-        ListUnitsItem {
-            name: t.0,
-            desc: t.1,
-            loaded,
-            active,
-            status,
-            other_name: t.5,
-            path: t.6,
-            idk: t.7,
-            idk2: t.8,
-            idk3: t.9,
-            unit_type,
-            base_name,
-        }
+    let mut errors = Vec::new();
+    let conns = connections(&args, &mut errors).await;
+    let parse_filter = |text: &str| {
+        let args = parse_line(text.split_whitespace())
+            .map_err(|e| e.to_string().lines().next().unwrap_or_default().to_string())?;
+        Ok(filter(&args))
+    };
+    let journal_dirs = match args.journal_dirs.is_empty() {
+        true => journal::DEFAULT_DIRS.iter().map(PathBuf::from).collect(),
+        false => args.journal_dirs.clone(),
+    };
+    // the bar starts with the line as given, `tui` is always the first word
+    let filter_text = std::env::args().skip(2).join(" ");
+    if let Err(e) = tui::run(
+        &conns,
+        filter(&args),
+        filter_text,
+        parse_filter,
+        &journal_dirs,
+    )
+    .await
+    {
+        eprintln!("ERROR: terminal: {e}");
+        exit(error::EXIT_IO);
     }
 }
 
-#[derive(Debug, Clone, Copy, strum::Display, PartialEq, Eq, PartialOrd, Ord)]
-#[strum(serialize_all = "UPPERCASE")]
-enum DaemonType {
-    User,
-    System,
-}
+/// `sys cat [OPTIONS] [PATTERNS]...`, see cat.rs
+#[derive(Args, Debug)]
+#[clap(arg_required_else_help(true))]
+#[clap(after_help = error::EXIT_STATUS_HELP)]
+struct CatSpec {
+    /// the unit file made from what systemd has for the unit now, defaults and drop-ins
+    /// included, instead of the files on disk
+    #[clap(long)]
+    effective: bool,
+
+    /// the files on disk too, with --effective
+    #[clap(long)]
+    files: bool,
 
-#[allow(unused)]
-#[derive(Debug)]
-struct Unit<'a> {
-    info: ListUnitsItem,
-    daemon: DaemonType,
-    //conn : &'a Connection,
-    manager: ManagerProxy<'a>,
-    proxy: UnitProxy<'a>,
+    #[clap(flatten)]
+    args: ArgSpec,
 }
 
-impl<'a> Unit<'a> {
-    async fn restart(&self) {
-        let span = span!(
-            Level::INFO,
-            "unit",
-            daemon = self.daemon.to_string(),
-            name = self.info.name
+async fn run_cat(cat: CatSpec) {
+    let args = &cat.args;
+    let filter = filter(args);
+    if filter.patterns.is_empty() && filter.templates.is_empty() {
+        eprintln!("ERROR: must specify unit or unit pattern for cat");
+        exit(error::EXIT_USAGE);
+    }
+
+    let mut errors = Vec::new();
+    let conns = connections(args, &mut errors).await;
+    let (mut all_units, unit_errors) = units_of(&conns, &filter).await;
+    for (name, e) in unit_errors {
+        report(&mut errors, name, e);
+    }
+    if all_units.values().all(Vec::is_empty) {
+        eprintln!(
+            "Filters [{}] matched no units.",
+            filter
+                .patterns
+                .iter()
+                .map(|re| format!("\'{:?}\'", re))
+                .join(if args.multi { " || " } else { " && " })
         );
-        let _guard = span.enter();
-        info!("request restart");
-        // default job mode https://www.freedesktop.org/software/systemd/man/systemctl.html#--job-mode=
-        self.proxy.restart("replace".to_string()).await.unwrap();
-        info!("pending");
-        /*
-        unit.proxy.receive_all_signals().await.unwrap().for_each(|v| {
-            let span = &span;
-            async move {
-                info!(parent: span, "{:?}", v)
+        exit(
+            errors
+                .first()
+                .map_or(error::EXIT_NOT_FOUND, SysError::exit_code),
+        )
+    }
+    load_units(&mut all_units, &mut errors).await;
+
+    let properties = PropertyCache::default();
+    let mut first = true;
+    for unit in all_units.values().flatten() {
+        if !std::mem::take(&mut first) {
+            println!();
+        }
+        if cat.effective {
+            match sys::cat::effective(unit, &properties).await {
+                Ok(text) => print!("{text}"),
+                Err(e) => report(&mut errors, format!("reading {}", unit.info.name), e),
+            }
+            if !cat.files {
+                continue;
+            }
+            println!();
+        }
+        let files = match sys::cat::files(unit, &properties).await {
+            Ok(files) => files,
+            Err(e) => {
+                report(&mut errors, format!("reading {}", unit.info.name), e);
+                continue;
             }
-        }).await;
-        */
+        };
+        if files.is_empty() {
+            println!(
+                "{}",
+                console::style(format!("# {} has no unit file", unit.info.name)).dim()
+            );
+        }
+        for (path, text) in files {
+            println!("{}", console::style(format!("# {path}")).dim());
+            match text {
+                Ok(text) => print!("{text}"),
+                Err(e) => report(&mut errors, format!("reading {}", unit.info.name), e),
+            }
+        }
     }
+    exit(errors.first().map_or(0, SysError::exit_code));
 }
 
 #[tokio::main]
 async fn main() {
-    // construct a subscriber that prints formatted traces to stdout
-    let subscriber = tracing_subscriber::FmtSubscriber::new();
-    // use that subscriber to process traces emitted after this point
-    tracing::subscriber::set_global_default(subscriber).unwrap();
+    let Cli { command, mut args } = Cli::parse();
+    match command {
+        Some(Command::Tui(args)) => return run_tui(args).await,
+        Some(Command::Cat(cat)) => return run_cat(cat).await,
+        Some(Command::Complete { args }) => {
+            let _ = completion::complete(&args).await;
+            return;
+        }
+        None => {}
+    }
+    if let Some(shell) = args.completions {
+        completion::print_script(shell);
+        return;
+    }
 
-    let mut args = ArgSpec::parse();
+    // stdout belongs to --output, if asked for
+    let mut output = (args.output != OutputFormat::Table).then(|| Output::new(args.output));
+
+    if console::Term::stderr().is_term() {
+        // spinners for the span tree, see progress.rs
+        let subscriber = tracing_subscriber::registry().with(progress::ProgressLayer::new());
+        tracing::subscriber::set_global_default(subscriber).unwrap();
+    } else if output.is_some() {
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .finish();
+        tracing::subscriber::set_global_default(subscriber).unwrap();
+    } else {
+        // construct a subscriber that prints formatted traces to stdout
+        let subscriber = tracing_subscriber::FmtSubscriber::new();
+        // use that subscriber to process traces emitted after this point
+        tracing::subscriber::set_global_default(subscriber).unwrap();
+    }
 
     if args.types.contains(&TypeOpt::_Any) {
         args.types = TypeOpt::variants().to_vec();
@@ -554,106 +660,12 @@ async fn main() {
         }
     }
 
-    let filters = {
-        let mut fixed_patterns = args
-            .fixed_strings
-            .iter()
-            .map(|s| {
-                let escaped = regex::escape(s);
-                let pattern = if !s.contains('.') {
-                    format!(r"^{}\.[^.]*$", escaped)
-                } else {
-                    format!(r"^{}$", escaped)
-                };
-                regex::Regex::new(&pattern).unwrap()
-            })
-            .collect();
-
-        let mut v = args.patterns.clone();
-        v.append(&mut fixed_patterns);
-        v
-    };
+    let filter = filter(&args);
 
-    let status_include = split_status_list(&args.status_filter);
-    let status_exclude = split_status_list(&args.status_filterx);
-    //let status_and = split_status_list(&args.status_filtera);
-
-    //println!("{:?}", filters);
-
-    let conns = {
-        let mut system = false;
-        let mut user = false;
-        if args.system_only {
-            system = true;
-        } else if args.user_only {
-            user = true;
-        } else {
-            match args.daemon {
-                DaemonOpt::User => user = true,
-                DaemonOpt::System => system = true,
-                DaemonOpt::Either => {
-                    user = true;
-                    system = true;
-                }
-            }
-        }
+    // failures that only concern some units, the first decides the exit code
+    let mut errors: Vec<SysError> = Vec::new();
 
-        let mut conns: Vec<(DaemonType, Connection)> = Vec::new();
-        if system {
-            let conn = Connection::system()
-                .await
-                .expect("could not connect to dbus system session");
-            conns.push((DaemonType::System, conn));
-        }
-        if user {
-            if users::get_current_uid() == 0 {
-                let uid_res = std::env::var("SUDO_UID").map(|v| {
-                    let n: u32 = v.parse().expect("non numeric uid");
-                    n
-                });
-                let uid =
-                    if let Ok(uid) = uid_res && uid != 0 {
-                        uid
-                    } else if system{
-                        //try to figure out with logind
-                        let _conn = &conns[0];
-                        //zbus_systemd::login1:: TODO
-                        todo!()
-                    } else {
-                        println!("ERROR: could not determine user uid");
-                        0
-                    };
-
-                // honor XDG_RUNTIME_DIR and DBUS_SESSION_BUS_ADDRESS
-                let address = std::env::var("DBUS_SESSION_BUS_ADDRESS").unwrap_or_else(|_| {
-                    let runtime_dir = std::env::var("XDG_RUNTIME_DIR")
-                        .ok()
-                        .unwrap_or_else(|| format!("/run/user/{}", uid));
-                    format!("unix:path={}/bus", runtime_dir)
-                });
-                let address: Address = Address::from_str(&address).unwrap();
-                println!("connecting to {}", address);
-
-                users::switch::set_effective_uid(uid).expect("could not set euid");
-                let conn = ConnectionBuilder::address(address)
-                    .expect("blarg")
-                    .build()
-                    .await
-                    .unwrap_or_else(|e| {
-                        panic!("could not connect to dbus user session (uid:{uid})\n{e}")
-                    });
-                conns.push((DaemonType::User, conn));
-                users::switch::set_effective_uid(0).unwrap();
-            } else {
-                // NOTE: i'm not sure what happens if there *is* a user session for root, like if you logged into a graphical session as root.
-                let conn = Connection::session()
-                    .await
-                    .expect("could not connect to dbus user session");
-                conns.push((DaemonType::User, conn));
-            }
-        }
-        conns
-    };
+    let conns = connections(&args, &mut errors).await;
 
     // used for print / prompt logic only atm
     let mut actions: Vec<&'static str> = Vec::new();
@@ -672,6 +684,19 @@ async fn main() {
     if args.disable {
         actions.push("Disable");
     }
+    if args.mask {
+        actions.push(match args.runtime {
+            true => "Mask until reboot",
+            false => "Mask",
+        });
+    }
+    if args.unmask {
+        actions.push("Unmask");
+    }
+    if args.output == OutputFormat::Csv && !actions.is_empty() {
+        eprintln!("ERROR: csv has only room for the units, use -o json or ndjson with actions");
+        exit(error::EXIT_USAGE);
+    }
 
     /*
     if filters.is_empty(){
@@ -681,380 +706,254 @@ async fn main() {
     }
     */
 
-    let mut all_units: BTreeMap<DaemonType, Vec<Unit>> = Default::default();
-    all_units.insert(DaemonType::User, Vec::new());
-    all_units.insert(DaemonType::System, Vec::new());
-
-    for (daemon, conn) in conns.iter() {
-        let manager = ManagerProxy::new(conn).await.unwrap();
-
-        if args.daemon_reload {
-            //todo  zbus_systemd::systemd1::Reloading
-            println!("{} {daemon} daemon", console::style("reload").bold());
-            manager
-                .reload()
-                .await
-                .unwrap_or_else(|e| panic!("problem reloading {daemon} daemon : {e}"))
+    if args.daemon_reload {
+        for (daemon, conn) in conns.iter() {
+            if output.is_none() {
+                println!("{} {} daemon", console::style("reload").bold(), daemon);
+            }
+            let reloaded = async { Daemon::new(*daemon, conn).await?.reload().await };
+            if let Err(e) = reloaded.await {
+                report(&mut errors, format!("reloading {daemon} daemon"), e);
+            }
         }
+    }
 
-        let units = manager.list_units().await.unwrap();
-        let units = units
-            .into_iter()
-            .map(ListUnitsItem::from)
-            .filter(|unit| match args.multi {
-                true => filters.iter().any(|re| re.is_match(&unit.name)),
-                false => filters.iter().all(|re| re.is_match(&unit.name)),
-            })
-            .filter(|unit| {
-                status_include.filter_includes(unit) && status_exclude.filter_excludes(unit)
-                //&&
-                //status_and.filter_and(unit)
-            })
-            .filter(|unit| args.types.contains(&unit.unit_type) || args.types.is_empty())
-            .map(|unit| async {
-                let proxy = UnitProxy::builder(conn)
-                    .path(unit.path.clone())
-                    .unwrap()
-                    .build()
-                    .await
-                    .unwrap();
-
-                if args.debug {
-                    println!("{:#?}", unit);
-                }
-
-                Unit {
-                    info: unit,
-                    daemon: *daemon,
-                    manager: manager.clone(),
-                    proxy,
-                }
-            })
-            .collect_vec();
-
-        let mut units = join_all(units).await;
-
-        let unit_file_names: HashSet<_> = units.iter().map(|v| v.info.name.clone()).collect();
-
-        // unloaded unit support
-        let unit_files = manager.list_unit_files().await.unwrap();
-        let unit_files = unit_files
-            .into_iter()
-            .map(UnitFile::new)
-            .filter(|unit| !unit_file_names.contains(&unit.name))
-            .filter(|unit| match args.multi {
-                true => filters.iter().any(|re| re.is_match(&unit.name)),
-                false => filters.iter().all(|re| re.is_match(&unit.name)),
-            })
-            .filter(|unit| args.types.contains(&unit.typ) || args.types.is_empty())
-            .filter(|u| {
-                if args.debug {
-                    println!("{:#?}", &u);
-                }
-                true
-            })
-            .map(|v| manager.load_unit(v.name))
-            .collect_vec();
-
-        let unit_files_loaded = join_all(unit_files)
-            .await
-            .into_iter()
-            .filter_map(Result::ok)
-            .map(|unit| async {
-                let unit = unit;
-                let proxy = UnitProxy::builder(conn)
-                    .path(unit.clone())
-                    .unwrap()
-                    .build()
-                    .await?;
-
-                if args.debug {
-                    println!("{:#?}", &unit);
-                }
-
-                let info = ListUnitsItem::from_proxy(&proxy).await;
-
-                Ok::<Unit, zbus::Error>(Unit {
-                    info,
-                    daemon: *daemon,
-                    manager: manager.clone(),
-                    proxy,
-                })
-            })
-            .collect_vec();
-
-        let unit_files_loaded = join_all(unit_files_loaded).await;
-        units.extend(unit_files_loaded.into_iter().filter_map(Result::ok));
-
-        units.sort_by_key(|v| v.info.name.clone());
-        all_units.get_mut(daemon).unwrap().extend(units);
+    let (mut all_units, unit_errors) = units_of(&conns, &filter).await;
+    for (name, e) in unit_errors {
+        report(&mut errors, name, e);
+    }
+    if args.debug {
+        for unit in all_units.values().flatten() {
+            println!("{:#?}", unit.info);
+        }
     }
 
     /* bail conditions*/
     {
-        if !actions.is_empty() && filters.is_empty() {
-            println!(
+        if !actions.is_empty() && filter.patterns.is_empty() && filter.templates.is_empty() {
+            eprintln!(
                 "ERROR: must specify unit or unit pattern for {}",
                 actions.join(", ")
             );
-            exit(1);
+            exit(error::EXIT_USAGE);
         }
 
         //TODO more consistant logic for when to print
-        if filters.is_empty() && args.daemon_reload {
-            exit(1);
+        if filter.patterns.is_empty() && args.daemon_reload {
+            exit(errors.first().map_or(0, SysError::exit_code));
         }
 
         if all_units.values().all(Vec::is_empty) {
-            println!(
+            if let Some(output) = output.take() {
+                written(output.finish(), &mut errors);
+            }
+            eprintln!(
                 "Filters [{}] matched no units.",
-                filters
+                filter
+                    .patterns
                     .iter()
                     .map(|re| format!("\'{:?}\'", re))
                     .join(if args.multi { " || " } else { " && " })
             );
-            exit(1)
+            exit(
+                errors
+                    .first()
+                    .map_or(error::EXIT_NOT_FOUND, SysError::exit_code),
+            )
         }
     }
 
-    /* print table */
+    let columns = match args.columns.is_empty() {
+        true => DEFAULT_COLUMNS.to_vec(),
+        false => args.columns.clone(),
+    };
+    if columns.iter().any(Column::reads_properties)
+        || args.sort.is_some_and(|sort| sort.reads_properties())
     {
-        use comfy_table::{
-            presets,
-            Attribute as Attr,
-            Cell,
-            ColumnConstraint,
-            ContentArrangement,
-            Row,
-            Table,
-        };
-        let mut table = Table::new();
-        table.load_preset(presets::NOTHING);
-        table.set_content_arrangement(ContentArrangement::Dynamic);
-
-        let mut longest = 0;
-        for (daemon, units) in all_units.iter() {
-            for unit in units {
-                let mut row = Row::default();
-                //0
-                row.add_cell(Cell::new(format!("{}:", daemon)).add_attribute(Attr::Dim));
-
-                //1
-                #[allow(clippy::iter_nth_zero)]
-                row.add_cell(Cell::new(format!(
-                    "{}-{}-{}",
-                    colorize_status(&unit.info.loaded.to_string())
-                        .apply_to(&unit.info.loaded.to_string().chars().nth(0).unwrap()),
-                    colorize_status(&unit.info.active.to_string())
-                        .apply_to(&unit.info.active.to_string().chars().nth(0).unwrap()),
-                    colorize_status(&unit.info.status.to_string())
-                        .apply_to(&unit.info.status.to_string().chars().nth(0).unwrap())
-                )));
-
-                //2
-                row.add_cell(Cell::new(format!(
-                    "{}",
-                    unit.info.unit_type.color_str(false)
-                )));
-
-                //3
-                row.add_cell(Cell::new(format!(
-                    "{}{}",
-                    unit.info.base_name,
-                    unit.info.unit_type.color_str(true)
-                )));
-
-                //4
-                row.add_cell(Cell::new(unit.info.base_name.to_string()));
-
-                //5
-                row.add_cell(Cell::new(
-                    colorize_status(&unit.info.loaded.to_string()).apply_to(&unit.info.loaded),
-                ));
-                //6
-                row.add_cell(Cell::new(
-                    colorize_status(&unit.info.active.to_string()).apply_to(&unit.info.active),
-                ));
-                //7
-                row.add_cell(Cell::new(
-                    colorize_status(&unit.info.status.to_string()).apply_to(&unit.info.status),
-                ));
-
-                //8
-                row.add_cell(Cell::new(&unit.info.desc).add_attribute(Attr::Italic));
-
-                let mut l = console::measure_text_width(&unit.info.name);
-                if args.verbose >= 2 {
-                    l += console::measure_text_width(&unit.info.desc);
-                }
-                longest = longest.max(l);
-                table.add_row(row);
-            }
+        load_units(&mut all_units, &mut errors).await;
+    }
+    let properties = PropertyCache::default();
+    let rows = columns::rows(
+        all_units.values().flatten(),
+        &columns,
+        args.sort,
+        &properties,
+    )
+    .await;
+
+    if let Some(output) = &mut output {
+        for (unit, _) in &rows {
+            written(output.unit(UnitRecord::new(unit)), &mut errors);
         }
+    } else {
+        let table = table(
+            &rows,
+            &columns,
+            &TableOptions {
+                picked: !args.columns.is_empty(),
+                verbose: args.verbose,
+                types: &args.types,
+                several_daemons: conns.len() > 1,
+                sorted: args.sort.is_some(),
+                no_abbr: args.no_abbr,
+            },
+        );
 
-        let width = console::Term::stdout().size_checked().unwrap_or((0, 200)).1 as usize;
-        let abbreviate = (longest + 40) > width || args.verbose < 3;
-        let abbreviate = abbreviate && !args.no_abbr;
-
-        // if daemon specified remove daemon column
-        if conns.len() < 2 {
-            table
-                .column_mut(0)
-                .unwrap()
-                .set_constraint(ColumnConstraint::Hidden);
+        // the status blocks already describe every unit
+        let status_only = args.status && actions.is_empty();
+        if !status_only && !(args.quiet && (args.force || table.row_iter().count() < 2)) {
+            println!("{}", table);
         }
+    }
 
-        if args.verbose == 0 || !abbreviate {
-            table
-                .column_mut(1)
-                .unwrap()
-                .set_constraint(ColumnConstraint::Hidden); //abbreviated
+    if !actions.is_empty() {
+        let count = all_units.values().flatten().count();
+        let terminal = std::io::stdin().is_terminal() && std::io::stderr().is_terminal();
+        if args.interactive && !terminal {
+            eprintln!("ERROR: -i needs a terminal to pick units on");
+            exit(error::EXIT_USAGE);
         }
-
-        #[allow(clippy::if_same_then_else)]
-        if args.types.is_empty() {
-            table
-                .column_mut(2)
-                .unwrap()
-                .set_constraint(ColumnConstraint::Hidden); //unit type
-            table
-                .column_mut(4)
-                .unwrap()
-                .set_constraint(ColumnConstraint::Hidden); //base name
-        } else if args.types.len() == 1 {
-            table
-                .column_mut(2)
-                .unwrap()
-                .set_constraint(ColumnConstraint::Hidden); //unit type
-            table
-                .column_mut(3)
-                .unwrap()
-                .set_constraint(ColumnConstraint::Hidden); //full name
-        } else {
-            table
-                .column_mut(3)
-                .unwrap()
-                .set_constraint(ColumnConstraint::Hidden); //full name
+        let pick = args.interactive || (terminal && count > PICK_OVER && !args.force);
+        let ask = pick || (count > 1 && !args.force);
+        if ask && !terminal {
+            eprintln!(
+                "ERROR: {count} units matched and there is no terminal to ask on, \
+                 pass -f to act on all of them"
+            );
+            exit(error::EXIT_USAGE);
         }
 
-        if args.verbose == 0 || abbreviate {
-            table
-                .column_mut(5)
-                .unwrap()
-                .set_constraint(ColumnConstraint::Hidden); //
-            table
-                .column_mut(6)
-                .unwrap()
-                .set_constraint(ColumnConstraint::Hidden); //
-            table
-                .column_mut(7)
-                .unwrap()
-                .set_constraint(ColumnConstraint::Hidden); //
+        if ask {
+            match confirm(&mut all_units, &actions, pick) {
+                Ok(true) => {}
+                Ok(false) => exit(0),
+                Err(e) => {
+                    eprintln!("aborted: {e}");
+                    exit(error::EXIT_IO);
+                }
+            }
         }
+    }
 
-        if args.verbose < 2 {
-            table
-                .column_mut(8)
-                .unwrap()
-                .set_constraint(ColumnConstraint::Hidden); //
+    // Execute actions
+    // unmask, enable disable, mask
+    let unit_file_actions = UnitFileActions {
+        unmask: args.unmask,
+        enable: args.enable,
+        disable: args.disable,
+        mask: args.mask,
+        runtime: args.runtime,
+        force: args.force,
+    };
+    if unit_file_actions.any() {
+        for unit in all_units.values().flatten() {
+            let done = unit_file_actions.apply(unit).await;
+            for (action, res) in done {
+                if let Some(output) = &mut output {
+                    written(
+                        output.action(ActionRecord::unit_file(unit, action, &res)),
+                        &mut errors,
+                    );
+                }
+                match res {
+                    Ok((file, changes)) if output.is_none() => {
+                        println!("{action} {}", file.as_ref().unwrap_or(&unit.info.name));
+                        for change in changes {
+                            println!("  {}", console::style(change).dim());
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => report(&mut errors, &unit.info.name, e),
+                }
+            }
         }
+    }
 
-        //TODO:
-        // if type specified remove type from name,
-        // if type not specified or only 1 type, remove type column
-        // if status specified remove status column
-        // if active specified remove active column
-        // if loaded specified remove loaded column
+    // jobs and status blocks need more than a unit file, following picks up units as they load
+    if args.stop || args.start || args.restart || args.status {
+        load_units(&mut all_units, &mut errors).await;
+    }
 
-        // if wraps, and not verbose remove desc
-        // if still wraps swap with abbreviated
+    let journal_dirs = match args.journal_dirs.is_empty() {
+        true => journal::DEFAULT_DIRS.iter().map(PathBuf::from).collect(),
+        false => args.journal_dirs.clone(),
+    };
 
-        if !(args.quiet && (args.force || table.row_iter().count() < 2)) {
-            println!("{}", table);
-        }
+    let mut results = Vec::new();
+    if args.stop || args.start || args.restart {
+        let mut journal = JournalFollower::new(&journal_dirs);
+
+        let jobs = [
+            (args.stop, UnitAction::Stop),
+            (args.start, UnitAction::Start),
+            (args.restart, UnitAction::Restart),
+        ]
+        .into_iter()
+        .filter_map(|(wanted, action)| wanted.then_some(action))
+        .collect_vec();
+        run_jobs(
+            &all_units,
+            &jobs,
+            &mut journal,
+            &properties,
+            |unit, action, result| {
+                if let Some(output) = &mut output {
+                    written(
+                        output.action(ActionRecord::job(unit, action, &result)),
+                        &mut errors,
+                    );
+                }
+                match result {
+                    Ok(result) => results.push(result),
+                    Err(e) => errors.push(e),
+                }
+            },
+        )
+        .await;
     }
 
-    #[allow(clippy::collapsible_if)]
-    if !actions.is_empty() {
-        if all_units.values().flatten().count() > 1 && !args.force {
-            let sty = console::Style::new().bold();
-            let actions = actions
-                .iter()
-                .map(|a| sty.apply_to(a).to_string())
-                .collect_vec();
+    if args.journal {
+        print_journal(&all_units, &journal_dirs, args.lines);
+    }
 
-            let actions_str = [
-                actions[..actions.len() - 1].join(", "),
-                actions[actions.len() - 1].to_string(),
-            ]
-            .iter()
-            .filter(|v| !v.is_empty())
-            .join(" and ");
+    if args.status {
+        let mut journal: HashMap<(DaemonType, &str), Vec<&journal::Entry>> = HashMap::new();
+        let entries = read_journal(&all_units, &journal_dirs);
+        for (entry, unit) in entries.iter() {
+            journal
+                .entry((unit.daemon, unit.info.name.as_str()))
+                .or_default()
+                .push(entry);
+        }
 
-            let confirm_str = all_units
-                .iter()
-                .filter_map(|(daemon, ls)| match ls.is_empty() {
-                    true => None,
-                    false => Some(format!(
-                        "{} {} units",
-                        ls.len(),
-                        daemon.to_string().to_lowercase()
-                    )),
-                })
-                .join(" and ");
-
-            let res = dialoguer::Confirm::new()
-                .with_prompt(format!("{} {}?", actions_str, confirm_str))
-                .default(false)
-                .interact()
-                .expect("abort");
-            /*
-            let names = all_units.values().flatten()
-                .map(|v| v.info.name.clone())
-                .collect_vec();
-            let res = dialoguer::FuzzySelect::new()
-                .items(&names)
-                .interact().expect("abort");
-            println!("{:?}", res);
-            */
-            if !res {
-                exit(0);
-            }
+        for unit in all_units.values().flatten() {
+            let entries = journal
+                .get(&(unit.daemon, unit.info.name.as_str()))
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let lines = args.lines.unwrap_or(10);
+            unit.print_status(&entries[entries.len().saturating_sub(lines)..], &properties)
+                .await;
+            println!();
         }
     }
 
-    // Execute actions
-    // enable disable
-    if args.enable || args.disable {
-        for unit in all_units.iter().flat_map(|v| v.1) {
-            let path = unit.proxy.fragment_path().await.unwrap();
-
-            if args.enable && args.disable {
-                println!("reenable {}", path);
-                unit.manager
-                    .reenable_unit_files(vec![path], false, args.force)
-                    .await
-                    .unwrap();
-            } else if args.enable {
-                println!("enable {}", path);
-                unit.manager
-                    .enable_unit_files(vec![path], false, args.force)
-                    .await
-                    .unwrap();
-            } else if args.disable {
-                println!("disable {}", path);
-                //XXX error thread 'main' panicked at 'called `Result::unwrap()` on an `Err` value: MethodError(OwnedErrorName(ErrorName(Str(Owned("org.freedesktop.DBus.Error.InvalidArgs")))), Some("Invalid argument"), Msg { type: Error, sender: UniqueName(Str(Borrowed(":1.0"))), reply-serial: 7, body: Signature("s") })', src/main.rs:799:74
-                unit.manager
-                    .disable_unit_files(vec![path], false)
-                    .await
-                    .unwrap();
-            }
-        }
+    if args.follow {
+        // status filters are left out, matched units are expected to change state
+        let matches = |unit: &ListUnitsItem| {
+            filter.matches_name(&unit.name) && filter.matches_type(&unit.unit_type)
+        };
+        follow::follow(&all_units, &conns, matches, &journal_dirs).await;
     }
 
-    if args.restart {
-        for unit in all_units.iter().flat_map(|v| v.1) {
-            unit.restart().await
-        }
+    if let Some(output) = output {
+        written(output.finish(), &mut errors);
+    }
+
+    if let Some(e) = errors.first() {
+        exit(e.exit_code());
+    }
+    if !results.iter().all(JobResult::is_success) {
+        exit(error::EXIT_JOB_FAILED);
     }
 }
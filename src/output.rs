@@ -0,0 +1,227 @@
+//! `--output`: the matched units and what we did to them, for scripts instead of people.
+//!
+//! json is one document with a `units` and an `actions` list, written once we are done.
+//! ndjson writes a line per unit or action as soon as we have it, told apart by `record`.
+//! csv writes the units only, one table with nowhere for actions, so the command line does
+//! not take it with any.
+use std::io::{
+    self,
+    Write,
+};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{
+    error::SysError,
+    state::{
+        ActiveState,
+        LoadState,
+        State,
+        SubState,
+    },
+    DaemonType,
+    JobResult,
+    TypeOpt,
+    Unit,
+    UnitAction,
+    UnitFileChange,
+    UnitFileStatus,
+};
+
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Ndjson,
+    Csv,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UnitRecord {
+    /// "system" or "user"
+    pub daemon: &'static str,
+    /// whose user daemon
+    pub uid: Option<u32>,
+    pub name: String,
+    pub base_name: String,
+    #[serde(rename = "type")]
+    pub unit_type: TypeOpt,
+    pub load_state: State<LoadState>,
+    pub active_state: State<ActiveState>,
+    pub sub_state: State<SubState>,
+    pub description: String,
+    /// None for a unit file nobody loaded yet
+    pub object_path: Option<String>,
+    /// None for units without a unit file
+    pub unit_file_state: Option<UnitFileStatus>,
+    /// foo@.service for its instances and itself
+    pub template: Option<String>,
+    /// bar of foo@bar.service
+    pub instance: Option<String>,
+}
+
+impl UnitRecord {
+    pub fn new(unit: &Unit<'_>) -> Self {
+        let (daemon, uid) = daemon_fields(unit.daemon);
+        UnitRecord {
+            daemon,
+            uid,
+            name: unit.info.name.clone(),
+            base_name: unit.info.base_name.clone(),
+            unit_type: unit.info.unit_type.clone(),
+            load_state: unit.info.loaded.clone(),
+            active_state: unit.info.active.clone(),
+            sub_state: unit.info.status.clone(),
+            description: unit.info.desc.clone(),
+            object_path: unit
+                .proxy
+                .as_ref()
+                .map(|proxy| proxy.inner().path().to_string()),
+            unit_file_state: unit.info.unit_file_state,
+            template: unit.info.template(),
+            instance: unit.info.instance().map(str::to_string),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActionRecord {
+    pub daemon: &'static str,
+    pub uid: Option<u32>,
+    pub unit: String,
+    /// start, stop, restart, enable, reenable, disable, mask or unmask
+    pub action: String,
+    /// how the job ended, for start, stop and restart
+    pub result: Option<JobResult>,
+    /// the unit file enable and reenable went by
+    pub file: Option<String>,
+    /// the symlinks unit file actions created and removed
+    pub changes: Vec<UnitFileChange>,
+    pub error: Option<String>,
+}
+
+impl ActionRecord {
+    fn new(unit: &Unit<'_>, action: impl ToString) -> Self {
+        let (daemon, uid) = daemon_fields(unit.daemon);
+        ActionRecord {
+            daemon,
+            uid,
+            unit: unit.info.name.clone(),
+            action: action.to_string(),
+            result: None,
+            file: None,
+            changes: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn job(unit: &Unit<'_>, action: UnitAction, result: &Result<JobResult, SysError>) -> Self {
+        let mut record = ActionRecord::new(unit, action);
+        match result {
+            Ok(result) => record.result = Some(result.clone()),
+            Err(e) => record.error = Some(e.to_string()),
+        }
+        record
+    }
+
+    /// enable, reenable, disable, mask and unmask, with the unit file they went by if any
+    pub fn unit_file(
+        unit: &Unit<'_>,
+        action: &str,
+        result: &Result<(Option<String>, Vec<UnitFileChange>), SysError>,
+    ) -> Self {
+        let mut record = ActionRecord::new(unit, action);
+        match result {
+            Ok((file, changes)) => {
+                record.file = file.clone();
+                record.changes = changes.clone();
+            }
+            Err(e) => record.error = Some(e.to_string()),
+        }
+        record
+    }
+}
+
+fn daemon_fields(daemon: DaemonType) -> (&'static str, Option<u32>) {
+    match daemon {
+        DaemonType::System => ("system", None),
+        DaemonType::User(uid) => ("user", Some(uid)),
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "record", rename_all = "lowercase")]
+enum Record<'r> {
+    Unit(&'r UnitRecord),
+    Action(&'r ActionRecord),
+}
+
+/// collects records and writes them to stdout in the chosen format
+#[derive(Debug)]
+pub struct Output {
+    format: OutputFormat,
+    units: Vec<UnitRecord>,
+    actions: Vec<ActionRecord>,
+}
+
+impl Output {
+    pub fn new(format: OutputFormat) -> Self {
+        Output {
+            format,
+            units: Vec::new(),
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn unit(&mut self, record: UnitRecord) -> io::Result<()> {
+        if self.format == OutputFormat::Ndjson {
+            write_line(&Record::Unit(&record))?;
+        }
+        self.units.push(record);
+        Ok(())
+    }
+
+    pub fn action(&mut self, record: ActionRecord) -> io::Result<()> {
+        if self.format == OutputFormat::Ndjson {
+            write_line(&Record::Action(&record))?;
+        }
+        self.actions.push(record);
+        Ok(())
+    }
+
+    /// write what json and csv held back
+    pub fn finish(self) -> io::Result<()> {
+        let stdout = io::stdout().lock();
+        match self.format {
+            OutputFormat::Table | OutputFormat::Ndjson => Ok(()),
+            OutputFormat::Json => {
+                #[derive(Serialize)]
+                struct Document<'r> {
+                    units: &'r [UnitRecord],
+                    actions: &'r [ActionRecord],
+                }
+                let document = Document {
+                    units: &self.units,
+                    actions: &self.actions,
+                };
+                let mut stdout = stdout;
+                serde_json::to_writer_pretty(&mut stdout, &document)?;
+                writeln!(stdout)
+            }
+            OutputFormat::Csv => {
+                let mut csv = csv::Writer::from_writer(stdout);
+                for record in &self.units {
+                    csv.serialize(record).map_err(io::Error::other)?;
+                }
+                csv.flush()
+            }
+        }
+    }
+}
+
+fn write_line(record: &Record) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer(&mut stdout, record)?;
+    writeln!(stdout)
+}
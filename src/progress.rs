@@ -0,0 +1,226 @@
+//! Renders our tracing spans as a tree of spinners.
+//!
+//! The command line action is the root span, each unit it acts on is a child, and the unit
+//! has short lived `state` and `journal` children while its job runs. A span that finished
+//! cleanly collapses to a single line, one that logged a warning or error stays expanded
+//! with the tail of what was logged below it.
+use std::{
+    collections::VecDeque,
+    fmt::{
+        Debug,
+        Write,
+    },
+    time::Duration,
+};
+
+use indicatif::{
+    MultiProgress,
+    ProgressBar,
+    ProgressStyle,
+};
+use tracing::{
+    field::{
+        Field,
+        Visit,
+    },
+    span,
+    Event,
+    Level,
+    Subscriber,
+};
+use tracing_subscriber::{
+    layer::Context,
+    registry::LookupSpan,
+    Layer,
+};
+
+/// log lines kept per span for when it fails
+const TAIL: usize = 10;
+
+#[derive(Default)]
+struct Fields {
+    message: Option<String>,
+    values: Vec<String>,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = Some(value.to_string()),
+            _ => self.values.push(value.to_string()),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        match field.name() {
+            "message" => self.message = Some(format!("{value:?}")),
+            _ => self.values.push(format!("{value:?}")),
+        }
+    }
+}
+
+struct Node {
+    bar: ProgressBar,
+    label: String,
+    depth: usize,
+    tail: VecDeque<String>,
+    failed: bool,
+}
+
+pub struct ProgressLayer {
+    multi: MultiProgress,
+}
+
+impl ProgressLayer {
+    pub fn new() -> Self {
+        ProgressLayer {
+            multi: MultiProgress::new(),
+        }
+    }
+
+    /// only our own spans and events, zbus has spans of its own
+    fn wants(metadata: &tracing::Metadata) -> bool {
+        metadata.target().starts_with(env!("CARGO_CRATE_NAME")) && *metadata.level() <= Level::INFO
+    }
+
+    fn style(depth: usize) -> ProgressStyle {
+        ProgressStyle::with_template(&format!("{}{{spinner:.green}} {{msg}}", "  ".repeat(depth)))
+            .unwrap()
+            .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏✔")
+    }
+
+    fn done_style(depth: usize) -> ProgressStyle {
+        ProgressStyle::with_template(&format!("{}{{msg}}", "  ".repeat(depth))).unwrap()
+    }
+}
+
+impl Default for ProgressLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for ProgressLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if !Self::wants(attrs.metadata()) {
+            return;
+        }
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        let label = match fields.values.is_empty() {
+            true => attrs.metadata().name().to_string(),
+            false => fields.values.join(" "),
+        };
+
+        let depth = span
+            .scope()
+            .skip(1)
+            .filter(|parent| parent.extensions().get::<Node>().is_some())
+            .count();
+
+        let bar = self.multi.add(ProgressBar::new_spinner());
+        bar.set_style(Self::style(depth));
+        bar.set_message(console::style(&label).bold().to_string());
+        bar.enable_steady_tick(Duration::from_millis(100));
+
+        span.extensions_mut().insert(Node {
+            bar,
+            label,
+            depth,
+            tail: VecDeque::new(),
+            failed: false,
+        });
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if !Self::wants(event.metadata()) {
+            return;
+        }
+
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let mut line = fields.message.unwrap_or_default();
+        for value in fields.values {
+            let _ = write!(line, " {value}");
+        }
+        let level = *event.metadata().level();
+        let failed = level <= Level::WARN;
+
+        let Some(span) = ctx.event_span(event) else {
+            // nothing to attach it to, print above the bars
+            let _ = self.multi.println(line);
+            return;
+        };
+
+        let mut first = true;
+        for span in span.scope() {
+            let mut extensions = span.extensions_mut();
+            let Some(node) = extensions.get_mut::<Node>() else {
+                continue;
+            };
+            if first {
+                let label = console::style(&node.label).bold();
+                let message = match failed {
+                    true => console::style(&line).red(),
+                    false => console::style(&line).dim(),
+                };
+                node.bar.set_message(format!("{label} {message}"));
+                first = false;
+            }
+            if node.tail.len() == TAIL {
+                node.tail.pop_front();
+            }
+            node.tail.push_back(line.clone());
+            node.failed |= failed;
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(node) = span.extensions_mut().remove::<Node>() else {
+            return;
+        };
+
+        // the journal and state spans only matter while the job runs
+        if node.depth > 1 {
+            node.bar.finish_and_clear();
+            return;
+        }
+
+        node.bar.set_style(Self::done_style(node.depth));
+        if !node.failed {
+            node.bar
+                .finish_with_message(format!("{} {}", console::style("✔").green(), node.label));
+            return;
+        }
+
+        let last = node.tail.back().cloned().unwrap_or_default();
+        node.bar.finish_with_message(format!(
+            "{} {} {}",
+            console::style("✘").red(),
+            node.label,
+            console::style(last).red()
+        ));
+
+        // only units expand, the root would repeat all of them
+        if node.depth == 0 {
+            return;
+        }
+        let mut after = node.bar.clone();
+        for line in node.tail.iter() {
+            let bar = self.multi.insert_after(&after, ProgressBar::new_spinner());
+            bar.set_style(Self::done_style(node.depth + 1));
+            bar.finish_with_message(console::style(line).dim().to_string());
+            after = bar;
+        }
+    }
+}
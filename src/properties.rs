@@ -0,0 +1,137 @@
+//! Unit properties a whole interface at a time, with one GetAll instead of a Get each.
+//!
+//! Tables and `-Q` read them through a [`PropertyCache`], so a unit is asked about once per
+//! run however many columns want something from it, and at most [`CONCURRENCY`] units are
+//! asked about at once.
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+
+use futures::{
+    stream,
+    StreamExt,
+};
+use zbus::{
+    fdo::PropertiesProxy,
+    names::InterfaceName,
+    zvariant::{
+        OwnedObjectPath,
+        OwnedValue,
+    },
+    Proxy,
+};
+
+use crate::{
+    error::SysError,
+    DaemonType,
+    TypeOpt,
+    Unit,
+};
+
+pub const UNIT_INTERFACE: &str = "org.freedesktop.systemd1.Unit";
+
+/// how many units are asked about at once, systemd answers one call at a time anyway
+pub const CONCURRENCY: usize = 16;
+
+/// the interface with what is particular to a unit type, like org.freedesktop.systemd1.Service
+pub fn type_interface(unit_type: &TypeOpt) -> String {
+    let unit_type = unit_type.to_string();
+    let mut chars = unit_type.chars();
+    let capitalized: String = chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default();
+    format!("org.freedesktop.systemd1.{capitalized}")
+}
+
+/// every property of one interface of one object
+#[derive(Debug, Clone, Default)]
+pub struct Properties(HashMap<String, OwnedValue>);
+
+impl Properties {
+    /// the properties of `interface` on the object behind `proxy`
+    pub async fn get_all(proxy: &Proxy<'_>, interface: &str) -> Result<Self, SysError> {
+        let interface = InterfaceName::try_from(interface)
+            .map_err(|_| SysError::parse("interface name", interface))?;
+        let properties = PropertiesProxy::builder(proxy.connection())
+            .destination(proxy.destination().to_owned())?
+            .path(proxy.path().to_owned())?
+            .build()
+            .await?;
+        let properties = properties
+            .get_all(interface)
+            .await
+            .map_err(zbus::Error::from)?;
+        Ok(Properties(properties))
+    }
+
+    /// every property by name, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &OwnedValue)> {
+        self.0.iter().map(|(name, value)| (name.as_str(), value))
+    }
+
+    pub fn value(&self, name: &str) -> Option<&OwnedValue> {
+        self.0.get(name)
+    }
+
+    /// None if it is not there or not a `T`
+    pub fn get<T: TryFrom<OwnedValue>>(&self, name: &str) -> Option<T> {
+        T::try_from(self.0.get(name)?.clone()).ok()
+    }
+}
+
+/// GetAll answers by daemon, object path and interface, for as long as they are current
+#[derive(Debug, Default)]
+pub struct PropertyCache {
+    entries: Mutex<HashMap<(DaemonType, OwnedObjectPath, String), Arc<Properties>>>,
+}
+
+impl PropertyCache {
+    /// from the cache, or from systemd if the unit was not asked about yet
+    pub async fn get(&self, unit: &Unit<'_>, interface: &str) -> Result<Arc<Properties>, SysError> {
+        let proxy = unit.proxy()?.inner();
+        let key = (
+            unit.daemon,
+            OwnedObjectPath::from(proxy.path().to_owned()),
+            interface.to_string(),
+        );
+        if let Some(properties) = self.entries.lock().unwrap().get(&key) {
+            return Ok(properties.clone());
+        }
+        let properties = Arc::new(Properties::get_all(proxy, interface).await?);
+        self.entries.lock().unwrap().insert(key, properties.clone());
+        Ok(properties)
+    }
+
+    /// drop what we have on a unit, after a job changed it
+    pub fn forget(&self, unit: &Unit<'_>) {
+        let Some(proxy) = &unit.proxy else {
+            return;
+        };
+        let path = proxy.inner().path();
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|(daemon, cached, _), _| !(*daemon == unit.daemon && cached.as_ref() == *path));
+    }
+}
+
+/// `f` of every unit, [`CONCURRENCY`] at a time, in the order of `units`
+pub async fn for_each_unit<'u, 'a: 'u, T, Fut>(
+    units: impl IntoIterator<Item = &'u Unit<'a>>,
+    f: impl FnMut(&'u Unit<'a>) -> Fut,
+) -> Vec<T>
+where
+    Fut: Future<Output = T>,
+{
+    stream::iter(units)
+        .map(f)
+        .buffered(CONCURRENCY)
+        .collect()
+        .await
+}
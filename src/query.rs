@@ -0,0 +1,336 @@
+//! Finding the units we are after, on one manager at a time.
+use std::collections::{
+    BTreeMap,
+    HashMap,
+    HashSet,
+};
+
+use itertools::Itertools;
+use regex::Regex;
+use tracing::warn;
+use zbus::Connection;
+use zbus_systemd::systemd1::ManagerProxy;
+
+use crate::{
+    error::SysError,
+    expr::Expr,
+    properties::for_each_unit,
+    state::StateFilter,
+    unit_file_name,
+    DaemonType,
+    ListUnitsItem,
+    TypeOpt,
+    Unit,
+    UnitFile,
+    UnitFileStatus,
+};
+
+/// which units we want, the default one wants all of them
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// regexes for the unit name
+    pub patterns: Vec<Regex>,
+    /// a name needs to match any pattern instead of all of them
+    pub any: bool,
+    /// empty for any type
+    pub types: Vec<TypeOpt>,
+    pub include: StateFilter,
+    pub exclude: StateFilter,
+    /// `-w`
+    pub expr: Option<Expr>,
+    /// leave out unit files nobody loaded yet
+    pub skip_unloaded: bool,
+    /// only instances of these templates, named like getty, getty@ or getty@.service
+    pub templates: Vec<String>,
+    /// only these instances, templates named by `templates` or a pattern like getty@ are made
+    /// into them
+    pub instances: Vec<String>,
+}
+
+/// getty, getty@ and getty@.service all name the template getty@.service
+fn names_template(name: &str, template: &str) -> bool {
+    match name.contains('.') {
+        true => name == template,
+        false => template
+            .split_once('@')
+            .is_some_and(|(prefix, _)| prefix == name.trim_end_matches('@')),
+    }
+}
+
+impl Filter {
+    /// a pattern for exactly this name, without a type it matches the name with any type
+    pub fn fixed_string(name: &str) -> Regex {
+        let escaped = regex::escape(name);
+        let pattern = if !name.contains('.') {
+            format!(r"^{}\.[^.]*$", escaped)
+        } else {
+            format!(r"^{}$", escaped)
+        };
+        Regex::new(&pattern).unwrap()
+    }
+
+    pub fn matches_name(&self, name: &str) -> bool {
+        match self.any {
+            true => self.patterns.iter().any(|re| re.is_match(name)),
+            false => self.patterns.iter().all(|re| re.is_match(name)),
+        }
+    }
+
+    pub fn matches_type(&self, unit_type: &TypeOpt) -> bool {
+        self.types.is_empty() || self.types.contains(unit_type)
+    }
+
+    pub fn matches_state(&self, unit: &ListUnitsItem) -> bool {
+        self.include.includes(unit) && self.exclude.excludes(unit)
+    }
+
+    /// instances of the templates, and the templates themselves
+    pub fn matches_template(&self, unit: &ListUnitsItem) -> bool {
+        self.templates.is_empty()
+            || unit.template().is_some_and(|template| {
+                self.templates
+                    .iter()
+                    .any(|name| names_template(name, &template))
+            })
+    }
+
+    /// the template is named by `--template` or a pattern, like getty, getty@ or
+    /// getty@.service, and not just matched by some regex
+    pub fn names_template(&self, template: &str) -> bool {
+        let patterns = self
+            .patterns
+            .iter()
+            .map(|re| re.as_str().trim_start_matches('^').trim_end_matches('$'))
+            .filter(|pattern| pattern.contains('@'));
+        self.templates
+            .iter()
+            .map(String::as_str)
+            .chain(patterns)
+            .any(|name| names_template(name, template))
+    }
+
+    /// templates pass too, [`Daemon::units`] makes the named ones into the instances
+    pub fn matches_instance(&self, unit: &ListUnitsItem) -> bool {
+        self.instances.is_empty()
+            || unit.is_template()
+            || unit
+                .instance()
+                .is_some_and(|instance| self.instances.iter().any(|i| i == instance))
+    }
+
+    pub fn matches_expr(&self, unit: &ListUnitsItem, daemon: DaemonType) -> bool {
+        self.expr
+            .as_ref()
+            .is_none_or(|expr| expr.eval(unit, daemon))
+    }
+
+    pub fn matches(&self, unit: &ListUnitsItem, daemon: DaemonType) -> bool {
+        self.matches_name(&unit.name)
+            && self.matches_template(unit)
+            && self.matches_instance(unit)
+            && self.matches_type(&unit.unit_type)
+            && self.matches_state(unit)
+            && self.matches_expr(unit, daemon)
+    }
+}
+
+/// a manager, the system one or a user's
+#[derive(Debug, Clone)]
+pub struct Daemon<'a> {
+    pub typ: DaemonType,
+    pub manager: ManagerProxy<'a>,
+}
+
+impl<'a> Daemon<'a> {
+    pub async fn new(typ: DaemonType, conn: &Connection) -> Result<Daemon<'a>, SysError> {
+        let manager = ManagerProxy::new(conn).await?;
+        // systemd only emits job signals to subscribed clients
+        if let Err(e) = manager.subscribe().await {
+            warn!("could not subscribe to {typ} daemon signals: {e}");
+        }
+        Ok(Daemon { typ, manager })
+    }
+
+    pub async fn reload(&self) -> Result<(), SysError> {
+        //todo  zbus_systemd::systemd1::Reloading
+        Ok(self.manager.reload().await?)
+    }
+
+    /// the units passing the filter sorted by name, unit files nobody loaded yet included
+    /// unless the filter skips them, see [`Unit::load`]. With instances asked for, templates
+    /// the filter names are replaced by those instances, loaded or not, other templates only
+    /// by the instances they already have.
+    /// Units that could not be looked at come back as (name, error) next to the rest.
+    pub async fn units(
+        &self,
+        filter: &Filter,
+    ) -> Result<(Vec<Unit<'a>>, Vec<(String, SysError)>), SysError> {
+        let mut errors = Vec::new();
+
+        let units = self.manager.list_units().await?;
+        let unit_files = match self.manager.list_unit_files().await {
+            Ok(unit_files) => unit_files,
+            Err(e) => {
+                errors.push((format!("listing {} unit files", self.typ), e.into()));
+                Vec::new()
+            }
+        };
+        let file_states: HashMap<&str, UnitFileStatus> = unit_files
+            .iter()
+            .filter_map(|(path, state)| {
+                Some((
+                    unit_file_name(std::path::Path::new(path))?,
+                    state.parse().ok()?,
+                ))
+            })
+            .collect();
+
+        // every loaded unit, not only the matching ones, the rest are left to the unit files
+        let unit_file_names: HashSet<_> = units.iter().map(|unit| unit.0.clone()).collect();
+        let units = units
+            .into_iter()
+            .filter(|unit| filter.matches_name(&unit.0))
+            .filter_map(|unit| {
+                let name = unit.0.clone();
+                ListUnitsItem::try_from(unit)
+                    .map_err(|e| errors.push((name, e)))
+                    .ok()
+            })
+            .map(|mut unit| {
+                unit.unit_file_state = file_states.get(unit.name.as_str()).copied();
+                unit
+            })
+            .filter(|unit| filter.matches(unit, self.typ))
+            .map(|info| Unit {
+                info,
+                daemon: self.typ,
+                manager: self.manager.clone(),
+                proxy: None,
+            })
+            .collect_vec();
+
+        let proxies = for_each_unit(&units, Unit::listed_proxy).await;
+        let mut units = units
+            .into_iter()
+            .zip(proxies)
+            .filter_map(|(unit, proxy)| match proxy {
+                Ok(proxy) => Some(Unit {
+                    proxy: Some(proxy),
+                    ..unit
+                }),
+                Err(e) => {
+                    errors.push((unit.info.name, e));
+                    None
+                }
+            })
+            .collect_vec();
+
+        // unit files nobody loaded yet, from what ListUnitFiles says alone. Loading them
+        // is left to what needs more, it is slow and has systemd keep them around
+        if !filter.skip_unloaded {
+            let unloaded = unit_files
+                .into_iter()
+                .filter(|(path, _)| {
+                    unit_file_name(std::path::Path::new(path)).is_none_or(|name| {
+                        filter.matches_name(name) && !unit_file_names.contains(name)
+                    })
+                })
+                .filter_map(|unit| {
+                    let path = unit.0.clone();
+                    UnitFile::new(unit).map_err(|e| errors.push((path, e))).ok()
+                })
+                .map(|unit_file| ListUnitsItem::unloaded(&unit_file))
+                .filter(|info| filter.matches(info, self.typ))
+                .map(|info| Unit {
+                    info,
+                    daemon: self.typ,
+                    manager: self.manager.clone(),
+                    proxy: None,
+                });
+            units.extend(unloaded);
+        }
+
+        // the named templates into the instances, those not listed yet are made on LoadUnit.
+        // Templates only some regex matched would make up instances nobody asked for
+        if !filter.instances.is_empty() {
+            let (templates, rest): (Vec<_>, Vec<_>) =
+                units.into_iter().partition(|unit| unit.info.is_template());
+            units = rest;
+            let templates = templates
+                .into_iter()
+                .filter(|unit| filter.names_template(&unit.info.name));
+            let listed: HashSet<String> = units.iter().map(|unit| unit.info.name.clone()).collect();
+            for template in templates {
+                for instance in &filter.instances {
+                    let info = template.info.instantiate(instance);
+                    // the name is the instance's, the patterns were for the template
+                    let matches = filter.matches_type(&info.unit_type)
+                        && filter.matches_state(&info)
+                        && filter.matches_expr(&info, self.typ);
+                    if matches && !listed.contains(&info.name) {
+                        units.push(Unit {
+                            info,
+                            proxy: None,
+                            ..template.clone()
+                        });
+                    }
+                }
+            }
+        }
+
+        units.sort_by_key(|v| v.info.name.clone());
+        Ok((units, errors))
+    }
+}
+
+/// the units passing the filter on each of the managers, see [`Daemon::units`]. Managers and
+/// units that could not be looked at come back as (what, error) next to the rest
+pub async fn units_of<'a>(
+    conns: &[(DaemonType, Connection)],
+    filter: &Filter,
+) -> (BTreeMap<DaemonType, Vec<Unit<'a>>>, Vec<(String, SysError)>) {
+    let mut all_units: BTreeMap<DaemonType, Vec<Unit<'a>>> = BTreeMap::new();
+    let mut errors = Vec::new();
+    for (typ, conn) in conns {
+        let daemon = match Daemon::new(*typ, conn).await {
+            Ok(daemon) => daemon,
+            Err(e) => {
+                errors.push((format!("{typ} daemon"), e));
+                continue;
+            }
+        };
+        match daemon.units(filter).await {
+            Ok((units, unit_errors)) => {
+                errors.extend(unit_errors);
+                all_units.entry(*typ).or_default().extend(units);
+            }
+            Err(e) => errors.push((format!("listing {typ} units"), e)),
+        }
+    }
+    (all_units, errors)
+}
+
+/// LoadUnit what so far is only a unit file, [`CONCURRENCY`](crate::properties::CONCURRENCY)
+/// at a time. The units that fail are dropped and come back as (name, error)
+pub async fn load_units(
+    all_units: &mut BTreeMap<DaemonType, Vec<Unit<'_>>>,
+) -> Vec<(String, SysError)> {
+    let mut errors = Vec::new();
+    for units in all_units.values_mut() {
+        // the loaded ones come back as they are
+        let loaded = for_each_unit(units.iter(), Unit::loaded).await;
+        let mut loaded = loaded.into_iter();
+        units.retain_mut(|unit| match loaded.next() {
+            Some(Ok(loaded)) => {
+                *unit = loaded;
+                true
+            }
+            Some(Err(e)) => {
+                errors.push((unit.info.name.clone(), e));
+                false
+            }
+            None => true,
+        });
+    }
+    errors
+}
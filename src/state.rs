@@ -0,0 +1,516 @@
+//! Unit states, after the tables in systemd's src/basic/unit-def.c.
+//!
+//! systemd grows new states now and then, anything we don't know is kept as [`State::Unknown`]
+//! with the string systemd sent, so it still prints and filters by name.
+use std::{
+    fmt::Display,
+    str::FromStr,
+};
+
+use clap::builder::{
+    NonEmptyStringValueParser,
+    TypedValueParser,
+};
+use itertools::Itertools;
+use strum::IntoEnumIterator;
+
+use crate::{
+    ListUnitsItem,
+    TypeOpt,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum State<T> {
+    Known(T),
+    Unknown(String),
+}
+
+impl<T> State<T> {
+    /// what systemd sent, if it is a name we don't know
+    pub fn unknown(&self) -> Option<&str> {
+        match self {
+            State::Known(_) => None,
+            State::Unknown(state) => Some(state),
+        }
+    }
+}
+
+impl<T: FromStr> From<&str> for State<T> {
+    fn from(s: &str) -> Self {
+        match s.parse() {
+            Ok(state) => State::Known(state),
+            Err(_) => State::Unknown(s.to_string()),
+        }
+    }
+}
+
+impl<T: Copy + Into<&'static str>> Display for State<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            State::Known(state) => f.pad((*state).into()),
+            State::Unknown(state) => f.pad(state),
+        }
+    }
+}
+
+/// serialized as the name systemd uses, like it is displayed
+impl<T: Copy + Into<&'static str>> serde::Serialize for State<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    strum::EnumString,
+    strum::IntoStaticStr,
+    strum::EnumIter,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum LoadState {
+    Stub,
+    Loaded,
+    NotFound,
+    BadSetting,
+    Error,
+    Merged,
+    Masked,
+    /// not systemd's: a unit file nobody loaded yet, which ListUnits leaves out
+    NotLoaded,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    strum::EnumString,
+    strum::IntoStaticStr,
+    strum::EnumIter,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum ActiveState {
+    Active,
+    Reloading,
+    Inactive,
+    Failed,
+    Activating,
+    Deactivating,
+    Maintenance,
+    Refreshing,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    strum::EnumString,
+    strum::IntoStaticStr,
+    strum::EnumIter,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum ServiceState {
+    Dead,
+    Condition,
+    StartPre,
+    Start,
+    StartPost,
+    Running,
+    Exited,
+    Reload,
+    ReloadSignal,
+    ReloadNotify,
+    Stop,
+    StopWatchdog,
+    StopSigterm,
+    StopSigkill,
+    StopPost,
+    FinalWatchdog,
+    FinalSigterm,
+    FinalSigkill,
+    Failed,
+    DeadBeforeAutoRestart,
+    FailedBeforeAutoRestart,
+    DeadResourcesPinned,
+    AutoRestart,
+    AutoRestartQueued,
+    Cleaning,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    strum::EnumString,
+    strum::IntoStaticStr,
+    strum::EnumIter,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum SocketState {
+    Dead,
+    StartPre,
+    StartChown,
+    StartPost,
+    Listening,
+    Running,
+    StopPre,
+    StopPreSigterm,
+    StopPreSigkill,
+    StopPost,
+    FinalSigterm,
+    FinalSigkill,
+    Failed,
+    Cleaning,
+}
+
+/// target and slice units are either there or not
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    strum::EnumString,
+    strum::IntoStaticStr,
+    strum::EnumIter,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum PresenceState {
+    Dead,
+    Active,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    strum::EnumString,
+    strum::IntoStaticStr,
+    strum::EnumIter,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum DeviceState {
+    Dead,
+    Tentative,
+    Plugged,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    strum::EnumString,
+    strum::IntoStaticStr,
+    strum::EnumIter,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum MountState {
+    Dead,
+    Mounting,
+    MountingDone,
+    Mounted,
+    Remounting,
+    Unmounting,
+    RemountingSigterm,
+    RemountingSigkill,
+    UnmountingSigterm,
+    UnmountingSigkill,
+    Failed,
+    Cleaning,
+}
+
+/// automount and path units share their states
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    strum::EnumString,
+    strum::IntoStaticStr,
+    strum::EnumIter,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum WatchState {
+    Dead,
+    Waiting,
+    Running,
+    Failed,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    strum::EnumString,
+    strum::IntoStaticStr,
+    strum::EnumIter,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum SwapState {
+    Dead,
+    Activating,
+    ActivatingDone,
+    Active,
+    Deactivating,
+    DeactivatingSigterm,
+    DeactivatingSigkill,
+    Failed,
+    Cleaning,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    strum::EnumString,
+    strum::IntoStaticStr,
+    strum::EnumIter,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum TimerState {
+    Dead,
+    Waiting,
+    Running,
+    Elapsed,
+    Failed,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    strum::EnumString,
+    strum::IntoStaticStr,
+    strum::EnumIter,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum ScopeState {
+    Dead,
+    StartChown,
+    Running,
+    Abandoned,
+    StopSigterm,
+    StopSigkill,
+    Failed,
+}
+
+/// the sub state, whose meaning depends on the unit type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SubState {
+    Service(ServiceState),
+    Socket(SocketState),
+    Target(PresenceState),
+    Device(DeviceState),
+    Mount(MountState),
+    Automount(WatchState),
+    Swap(SwapState),
+    Timer(TimerState),
+    Path(WatchState),
+    Slice(PresenceState),
+    Scope(ScopeState),
+}
+
+impl SubState {
+    pub(crate) fn parse(unit_type: &TypeOpt, s: &str) -> State<SubState> {
+        fn known<T: FromStr>(s: &str, f: fn(T) -> SubState) -> Option<SubState> {
+            s.parse().ok().map(f)
+        }
+        let state = match unit_type {
+            TypeOpt::Service => known(s, SubState::Service),
+            TypeOpt::Socket => known(s, SubState::Socket),
+            TypeOpt::Target => known(s, SubState::Target),
+            TypeOpt::Device => known(s, SubState::Device),
+            TypeOpt::Mount => known(s, SubState::Mount),
+            TypeOpt::Automount => known(s, SubState::Automount),
+            TypeOpt::Swap => known(s, SubState::Swap),
+            TypeOpt::Timer => known(s, SubState::Timer),
+            TypeOpt::Path => known(s, SubState::Path),
+            TypeOpt::Slice => known(s, SubState::Slice),
+            TypeOpt::Scope => known(s, SubState::Scope),
+            TypeOpt::_Any | TypeOpt::Other(_) => None,
+        };
+        match state {
+            Some(state) => State::Known(state),
+            None => State::Unknown(s.to_string()),
+        }
+    }
+
+    /// every sub state name of every type, once each
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        fn names<T: IntoEnumIterator + Into<&'static str>>() -> impl Iterator<Item = &'static str> {
+            T::iter().map(Into::into)
+        }
+        names::<ServiceState>()
+            .chain(names::<SocketState>())
+            .chain(names::<PresenceState>())
+            .chain(names::<DeviceState>())
+            .chain(names::<MountState>())
+            .chain(names::<WatchState>())
+            .chain(names::<SwapState>())
+            .chain(names::<TimerState>())
+            .chain(names::<ScopeState>())
+            .unique()
+    }
+}
+
+impl From<SubState> for &'static str {
+    fn from(state: SubState) -> Self {
+        match state {
+            SubState::Service(state) => state.into(),
+            SubState::Socket(state) => state.into(),
+            SubState::Target(state) | SubState::Slice(state) => state.into(),
+            SubState::Device(state) => state.into(),
+            SubState::Mount(state) => state.into(),
+            SubState::Automount(state) | SubState::Path(state) => state.into(),
+            SubState::Swap(state) => state.into(),
+            SubState::Timer(state) => state.into(),
+            SubState::Scope(state) => state.into(),
+        }
+    }
+}
+
+/// what `-s` and `-x` match against, sub states go by name across unit types
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateOpt {
+    Load(LoadState),
+    Active(ActiveState),
+    Sub(&'static str),
+    /// a name we don't know, for the states systemd sent that we don't know either
+    Unknown(String),
+}
+
+/// prefix for sub states that share their name with an active state, like `status-failed`
+const SUB_PREFIX: &str = "status-";
+
+impl StateOpt {
+    fn from_name(name: &str) -> Self {
+        let name = name.to_ascii_lowercase();
+        if let Ok(state) = name.parse() {
+            return StateOpt::Load(state);
+        }
+        if let Ok(state) = name.parse() {
+            return StateOpt::Active(state);
+        }
+        let sub = name.strip_prefix(SUB_PREFIX).unwrap_or(&name);
+        match SubState::names().find(|known| *known == sub) {
+            Some(sub) => StateOpt::Sub(sub),
+            None => StateOpt::Unknown(name),
+        }
+    }
+
+    /// clap parser for any name, any case, systemd may use names newer than ours
+    pub fn value_parser() -> impl TypedValueParser<Value = StateOpt> {
+        NonEmptyStringValueParser::new().map(|name| StateOpt::from_name(&name))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StateFilter {
+    loaded: Vec<LoadState>,
+    active: Vec<ActiveState>,
+    sub: Vec<&'static str>,
+    unknown: Vec<String>,
+}
+
+impl StateFilter {
+    pub fn new(l: &[StateOpt]) -> Self {
+        let mut ret = StateFilter::default();
+        for state in l {
+            match state {
+                StateOpt::Load(state) => ret.loaded.push(*state),
+                StateOpt::Active(state) => ret.active.push(*state),
+                StateOpt::Sub(state) => ret.sub.push(state),
+                StateOpt::Unknown(name) => ret.unknown.push(name.clone()),
+            }
+        }
+        ret
+    }
+
+    fn is_empty(&self) -> bool {
+        self.loaded.is_empty()
+            && self.active.is_empty()
+            && self.sub.is_empty()
+            && self.unknown.is_empty()
+    }
+
+    fn matches(&self, unit: &ListUnitsItem) -> bool {
+        matches!(&unit.loaded, State::Known(state) if self.loaded.contains(state))
+            || matches!(&unit.active, State::Known(state) if self.active.contains(state))
+            || self.sub.contains(&unit.status.to_string().as_str())
+            || [
+                unit.loaded.unknown(),
+                unit.active.unknown(),
+                unit.status.unknown(),
+            ]
+            .into_iter()
+            .flatten()
+            .any(|state| {
+                self.unknown
+                    .iter()
+                    .any(|name| state.eq_ignore_ascii_case(name))
+            })
+    }
+
+    /// match by any
+    pub fn includes(&self, unit: &ListUnitsItem) -> bool {
+        self.is_empty() || self.matches(unit)
+    }
+
+    /// match by none
+    pub fn excludes(&self, unit: &ListUnitsItem) -> bool {
+        self.is_empty() || !self.matches(unit)
+    }
+}
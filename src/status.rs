@@ -0,0 +1,540 @@
+//! What `-Q` and `-L` print, and the colors used throughout.
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+        HashSet,
+    },
+    fmt::Display,
+    path::PathBuf,
+};
+
+use console::Style;
+use itertools::Itertools;
+use tracing::{
+    debug,
+    warn,
+};
+use zbus_systemd::systemd1::{
+    ServiceProxy,
+    SocketProxy,
+};
+
+use crate::{
+    journal,
+    properties::{
+        type_interface,
+        PropertyCache,
+        UNIT_INTERFACE,
+    },
+    DaemonType,
+    TypeOpt,
+    Unit,
+};
+
+pub fn colorize_status(text: &str) -> Style {
+    let style = Style::new();
+    match text {
+        "loaded" => style.dim(),
+        "not-found" => style.yellow(),
+        "bad-setting" | "error" => style.red(),
+        "masked" | "stub" | "merged" | "not-loaded" => style.dim(),
+        "active" => style,
+        "actives" => style,
+        "inactive" => style.dim(),
+        "failed" => style.red(),
+        "maintenance" => style.yellow(),
+        "dead" => style.yellow(),
+        "running" => style.green(),
+        "plugged" => style,
+        "tentative" => style.yellow().dim(),
+        "mounted" => style,
+        "waiting" => style.green().dim(),
+        "exited" => style.dim(),
+        "elapsed" => style.dim(),
+        "abandoned" => style.dim(),
+        "listening" => style.green().dim(),
+        "auto-restart" | "auto-restart-queued" => style.yellow(),
+        // unit file states
+        "enabled" | "enabled-runtime" => style.green(),
+        "disabled" => style.yellow().dim(),
+        "static" | "indirect" | "alias" | "generated" | "transient" => style.dim(),
+        "masked-runtime" | "linked" | "linked-runtime" => style.dim(),
+        "bad" => style.red(),
+        // on the way somewhere else: activating, start-pre, stop-sigterm, remounting, ...
+        transition
+            if [
+                "activat",
+                "deactivat",
+                "reload",
+                "refresh",
+                "start",
+                "stop",
+                "final",
+            ]
+            .iter()
+            .chain(&[
+                "mounting",
+                "remounting",
+                "unmounting",
+                "condition",
+                "cleaning",
+            ])
+            .any(|prefix| transition.starts_with(prefix)) =>
+        {
+            style.yellow()
+        }
+        _ => style,
+    }
+}
+
+impl<'a> Unit<'a> {
+    /// a detailed block in the spirit of `systemctl status`
+    pub async fn print_status(&self, journal: &[&journal::Entry], cache: &PropertyCache) {
+        let Ok(proxy) = self.proxy() else {
+            println!(
+                "{} {}{} - {}",
+                console::style("○").dim(),
+                self.info.base_name,
+                self.info.unit_type.color_str(true),
+                console::style("not loaded").dim()
+            );
+            return;
+        };
+        let conn = proxy.inner().connection();
+        let path = proxy.inner().path().clone();
+        let properties = cache.get(self, UNIT_INTERFACE).await.unwrap_or_default();
+        let string = |name: &str| properties.get::<String>(name).unwrap_or_default();
+        let strings = |name: &str| properties.get::<Vec<String>>(name).unwrap_or_default();
+        let field = |label: &str, value: &dyn Display| {
+            println!(
+                "{:>14} {}",
+                console::style(format!("{label}:")).bold(),
+                value
+            )
+        };
+        let more = |value: &dyn Display| println!("{:>14} {}", "", value);
+
+        let active = self.info.active.to_string();
+        let dot = colorize_status(&active).apply_to("●");
+        let desc = string("Description");
+        println!(
+            "{} {}{} - {}",
+            dot,
+            self.info.base_name,
+            self.info.unit_type.color_str(true),
+            desc
+        );
+        field("Daemon", &self.daemon);
+
+        let loaded = self.info.loaded.to_string();
+        let fragment = string("FragmentPath");
+        let file_state = string("UnitFileState");
+        let preset = string("UnitFilePreset");
+        let details = [
+            fragment,
+            file_state,
+            match preset.is_empty() {
+                true => preset,
+                false => format!("preset: {preset}"),
+            },
+        ]
+        .into_iter()
+        .filter(|v| !v.is_empty())
+        .join("; ");
+        field(
+            "Loaded",
+            &format!("{} ({details})", colorize_status(&loaded).apply_to(&loaded)),
+        );
+
+        let drop_ins = strings("DropInPaths");
+        for (i, drop_in) in drop_ins.iter().enumerate() {
+            match i {
+                0 => field("Drop-In", drop_in),
+                _ => more(drop_in),
+            }
+        }
+
+        let sub = self.info.status.to_string();
+        let since = properties
+            .get::<u64>("StateChangeTimestamp")
+            .unwrap_or_default();
+        let since = match format_timestamp(since, "%a %Y-%m-%d %H:%M:%S %Z") {
+            Some(ts) => {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_micros() as u64;
+                let ago = format_timespan(now.saturating_sub(since));
+                format!(" since {ts}; {ago} ago")
+            }
+            None => String::new(),
+        };
+        field(
+            "Active",
+            &format!(
+                "{} ({}){since}",
+                colorize_status(&active).apply_to(&active),
+                colorize_status(&sub).apply_to(&sub)
+            ),
+        );
+
+        // timers say what they trigger below, with when
+        let triggers = strings("Triggers");
+        if !triggers.is_empty() && self.info.unit_type != TypeOpt::Timer {
+            field("Triggers", &triggers.join(", "));
+        }
+        let triggered_by = strings("TriggeredBy");
+        if !triggered_by.is_empty() {
+            field("TriggeredBy", &triggered_by.join(", "));
+        }
+
+        let mut main_pid = None;
+        let mut tasks = None;
+        let mut memory = None;
+        let mut cpu = None;
+        let mut cgroup = None;
+        let mut processes = Vec::new();
+
+        let typed = match self.info.unit_type {
+            TypeOpt::Service | TypeOpt::Socket | TypeOpt::Timer => cache
+                .get(self, &type_interface(&self.info.unit_type))
+                .await
+                .unwrap_or_default(),
+            _ => Default::default(),
+        };
+        // systemd reports u64::MAX for accounting it doesn't track
+        let accounted = |name: &str| typed.get::<u64>(name).filter(|v| *v != u64::MAX);
+        if matches!(self.info.unit_type, TypeOpt::Service | TypeOpt::Socket) {
+            tasks = accounted("TasksCurrent");
+            memory = accounted("MemoryCurrent");
+            cpu = accounted("CPUUsageNSec");
+            cgroup = typed.get::<String>("ControlGroup");
+        }
+
+        match self.info.unit_type {
+            TypeOpt::Service => {
+                main_pid = typed.get::<u32>("MainPID").filter(|pid| *pid != 0);
+                let service = async { ServiceProxy::builder(conn).path(path)?.build().await };
+                if let Ok(service) = service.await {
+                    processes = service.get_processes().await.unwrap_or_default();
+                }
+            }
+            TypeOpt::Socket => {
+                let listen = typed
+                    .get::<Vec<(String, String)>>("Listen")
+                    .unwrap_or_default();
+                for (i, (typ, address)) in listen.iter().enumerate() {
+                    let line = format!("{address} ({typ})");
+                    match i {
+                        0 => field("Listen", &line),
+                        _ => more(&line),
+                    }
+                }
+                if let (Some(accepted), Some(connected)) = (
+                    typed.get::<u32>("NAccepted"),
+                    typed.get::<u32>("NConnections"),
+                ) {
+                    field("Accepted", &format!("{accepted}; Connected: {connected}"));
+                }
+                let socket = async { SocketProxy::builder(conn).path(path)?.build().await };
+                if let Ok(socket) = socket.await {
+                    processes = socket.get_processes().await.unwrap_or_default();
+                }
+            }
+            TypeOpt::Timer => {
+                let next = typed
+                    .get::<u64>("NextElapseUSecRealtime")
+                    .unwrap_or_default();
+                if let Some(next) = format_timestamp(next, "%a %Y-%m-%d %H:%M:%S %Z") {
+                    field("Trigger", &next);
+                }
+                let last = typed.get::<u64>("LastTriggerUSec").unwrap_or_default();
+                if let Some(last) = format_timestamp(last, "%a %Y-%m-%d %H:%M:%S %Z") {
+                    field("Last trigger", &last);
+                }
+                if let Some(unit) = typed.get::<String>("Unit") {
+                    field("Triggers", &unit);
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(pid) = main_pid {
+            field("Main PID", &pid);
+        }
+        if let Some(tasks) = tasks {
+            field("Tasks", &tasks);
+        }
+        if let Some(memory) = memory {
+            field("Memory", &format_bytes(memory));
+        }
+        if let Some(cpu) = cpu {
+            field("CPU", &format_timespan(cpu / 1000));
+        }
+        if let Some(cgroup) = cgroup.filter(|c| !c.is_empty()) {
+            field("CGroup", &cgroup);
+            for line in cgroup_tree(&cgroup, &processes) {
+                more(&line);
+            }
+        }
+
+        let timestamps = [
+            ("Active enter", "ActiveEnterTimestamp"),
+            ("Active exit", "ActiveExitTimestamp"),
+            ("Inactive enter", "InactiveEnterTimestamp"),
+            ("Inactive exit", "InactiveExitTimestamp"),
+        ];
+        for (label, name) in timestamps {
+            let usec = properties.get::<u64>(name).unwrap_or_default();
+            if let Some(ts) = format_timestamp(usec, "%a %Y-%m-%d %H:%M:%S %Z") {
+                field(label, &ts);
+            }
+        }
+
+        if !journal.is_empty() {
+            println!();
+            for entry in journal {
+                println!("{}", format_journal_entry(entry, self));
+            }
+        }
+    }
+}
+
+/// render GetProcesses output as a tree below the unit's cgroup
+fn cgroup_tree(root: &str, processes: &[(String, u32, String)]) -> Vec<String> {
+    let mut groups: BTreeMap<&str, Vec<(u32, &str)>> = BTreeMap::new();
+    for (group, pid, cmd) in processes {
+        groups
+            .entry(group.as_str())
+            .or_default()
+            .push((*pid, cmd.as_str()));
+    }
+    let root_processes = groups.remove(root).unwrap_or_default();
+
+    let branch = |last: bool| if last { "└─" } else { "├─" };
+    let total = root_processes.len() + groups.len();
+    let mut lines = Vec::new();
+    let mut i = 0;
+
+    for (pid, cmd) in root_processes {
+        i += 1;
+        lines.push(format!("{}{pid} {cmd}", branch(i == total)));
+    }
+    for (group, processes) in groups {
+        i += 1;
+        let last = i == total;
+        let name = group
+            .strip_prefix(root)
+            .unwrap_or(group)
+            .trim_start_matches('/');
+        lines.push(format!("{}{name}", branch(last)));
+        let indent = if last { "  " } else { "│ " };
+        for (j, (pid, cmd)) in processes.iter().enumerate() {
+            lines.push(format!(
+                "{indent}{}{pid} {cmd}",
+                branch(j == processes.len() - 1)
+            ));
+        }
+    }
+    lines
+}
+
+/// the (daemon, unit) pairs a journal entry could belong to, most specific first
+pub fn journal_units(entry: &journal::Entry) -> impl Iterator<Item = (DaemonType, &str)> {
+    // whose user manager, the owner is set for processes in a user slice
+    let owner = entry
+        .field("_SYSTEMD_OWNER_UID")
+        .or_else(|| entry.field("_UID"))
+        .and_then(|uid| uid.parse().ok())
+        .map(DaemonType::User);
+    [
+        (owner, "_SYSTEMD_USER_UNIT"),
+        (owner, "USER_UNIT"),
+        (Some(DaemonType::System), "_SYSTEMD_UNIT"),
+        (Some(DaemonType::System), "UNIT"),
+    ]
+    .into_iter()
+    .filter_map(|(daemon, field)| Some((daemon?, entry.field(field)?)))
+}
+
+/// journal entries belonging to any of the units, in chronological order
+pub fn read_journal<'u, 'a>(
+    all_units: &'u BTreeMap<DaemonType, Vec<Unit<'a>>>,
+    dirs: &[PathBuf],
+) -> Vec<(journal::Entry, &'u Unit<'a>)> {
+    let wanted: HashMap<(DaemonType, &str), &Unit> = all_units
+        .iter()
+        .flat_map(|(daemon, units)| units.iter().map(|u| ((*daemon, u.info.name.as_str()), u)))
+        .collect();
+    let names = wanted
+        .keys()
+        .map(|(daemon, name)| (*daemon, name.to_string()))
+        .collect();
+
+    read_unit_journal(&names, dirs)
+        .into_iter()
+        .filter_map(|entry| {
+            let unit = journal_units(&entry).find_map(|key| wanted.get(&key).copied())?;
+            Some((entry, unit))
+        })
+        .collect()
+}
+
+/// [`read_journal`] by the names of the units, for where the units can't go, like a blocking
+/// task
+pub fn read_unit_journal(
+    units: &HashSet<(DaemonType, String)>,
+    dirs: &[PathBuf],
+) -> Vec<journal::Entry> {
+    // the fields journal_units goes by, looked up in each file rather than read from every entry
+    let matches = units
+        .iter()
+        .flat_map(|(daemon, name)| {
+            match daemon {
+                DaemonType::System => ["_SYSTEMD_UNIT", "UNIT"],
+                DaemonType::User(_) => ["_SYSTEMD_USER_UNIT", "USER_UNIT"],
+            }
+            .map(|field| format!("{field}={name}"))
+        })
+        .sorted()
+        .dedup()
+        .collect_vec();
+
+    let mut entries = Vec::new();
+    for path in journal::journal_files(dirs) {
+        let file = match journal::JournalFile::open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                warn!("could not read journal {}: {e}", path.display());
+                continue;
+            }
+        };
+
+        for entry in file.entries_matching(&matches) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    debug!("stopped reading {}: {e}", file.path().display());
+                    break;
+                }
+            };
+            // a match on the name alone can still be another user's unit
+            let wanted = journal_units(&entry)
+                .any(|(daemon, name)| units.contains(&(daemon, name.to_string())));
+            if wanted {
+                entries.push(entry);
+            }
+        }
+    }
+
+    // files are each in order, but interleave with each other
+    entries.sort_by_key(|entry| entry.realtime);
+    entries
+}
+
+pub fn format_journal_entry(entry: &journal::Entry, unit: &Unit) -> String {
+    let time = format_timestamp(entry.realtime, "%b %d %H:%M:%S").unwrap_or_default();
+
+    let pid = entry
+        .field("_PID")
+        .map(|pid| format!("[{pid}]"))
+        .unwrap_or_default();
+
+    let message = entry.message();
+    let message = match entry.priority() {
+        0..=3 => console::style(message).red().bold(),
+        4 => console::style(message).yellow(),
+        5 => console::style(message).bold(),
+        7 => console::style(message).dim(),
+        _ => console::style(message),
+    };
+
+    format!(
+        "{} {} {}{}{}: {}",
+        console::style(time).dim(),
+        console::style(format!("{}:", unit.daemon)).dim(),
+        unit.info.base_name,
+        unit.info.unit_type.color_str(true),
+        pid,
+        message
+    )
+}
+
+pub fn print_journal(
+    all_units: &BTreeMap<DaemonType, Vec<Unit>>,
+    dirs: &[PathBuf],
+    lines: Option<usize>,
+) {
+    let entries = read_journal(all_units, dirs);
+    let skip = lines.map_or(0, |n| entries.len().saturating_sub(n));
+
+    for (entry, unit) in entries.iter().skip(skip) {
+        println!("{}", format_journal_entry(entry, unit));
+    }
+}
+
+/// usec since the epoch in local time, None for systemd's "never" (0)
+pub fn format_timestamp(usec: u64, fmt: &str) -> Option<String> {
+    use chrono::TimeZone;
+
+    if usec == 0 || usec == u64::MAX {
+        return None;
+    }
+    chrono::Local
+        .timestamp_opt((usec / 1_000_000) as i64, (usec % 1_000_000) as u32 * 1000)
+        .single()
+        .map(|t| t.format(fmt).to_string())
+}
+
+/// like systemd's format_timespan, but only the two most significant parts
+pub fn format_timespan(usec: u64) -> String {
+    timespan(usec, 2)
+}
+
+/// to the microsecond, for where it is read back like in a unit file
+pub fn format_timespan_exact(usec: u64) -> String {
+    timespan(usec, usize::MAX)
+}
+
+/// the largest `parts` units of `usec`, like "1min 30s"
+fn timespan(usec: u64, parts: usize) -> String {
+    const UNITS: [(&str, u64); 6] = [
+        ("d", 86_400_000_000),
+        ("h", 3_600_000_000),
+        ("min", 60_000_000),
+        ("s", 1_000_000),
+        ("ms", 1_000),
+        ("us", 1),
+    ];
+
+    let mut rest = usec;
+    let parts = UNITS
+        .iter()
+        .filter_map(|(name, size)| {
+            let n = rest / size;
+            rest %= size;
+            (n > 0).then(|| format!("{n}{name}"))
+        })
+        .take(parts)
+        .collect_vec();
+
+    match parts.is_empty() {
+        true => "0".to_string(),
+        false => parts.join(" "),
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "K", "M", "G", "T", "P"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes}B"),
+        _ => format!("{value:.1}{}", UNITS[unit]),
+    }
+}
@@ -0,0 +1,140 @@
+//! The table `sys` prints the units in. Without `--columns` it starts from
+//! [`DEFAULT_COLUMNS`](crate::columns::DEFAULT_COLUMNS) and hides what `-v`, `-t` and the
+//! width of the terminal leave no room for.
+use std::collections::HashSet;
+
+use comfy_table::{
+    presets,
+    Cell,
+    ColumnConstraint,
+    ContentArrangement,
+    Table,
+};
+
+use crate::{
+    columns::{
+        Column,
+        Details,
+    },
+    DaemonType,
+    TypeOpt,
+    Unit,
+};
+
+/// what the command line says about the table
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TableOptions<'t> {
+    /// the columns were picked with `--columns`, they get headers and none are hidden
+    pub picked: bool,
+    /// `-v`, the more the more columns
+    pub verbose: u8,
+    /// `-t`
+    pub types: &'t [TypeOpt],
+    /// units of more than one daemon are listed
+    pub several_daemons: bool,
+    /// `--sort`, instances are only grouped under their template in the default order
+    pub sorted: bool,
+    /// `--no-abbr`
+    pub no_abbr: bool,
+}
+
+/// the rows as [`columns::rows`](crate::columns::rows) gave them, in their order
+pub fn table(rows: &[(&Unit<'_>, Details)], columns: &[Column], options: &TableOptions) -> Table {
+    let mut table = Table::new();
+    table.load_preset(presets::NOTHING);
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    if options.picked {
+        table.set_header(
+            columns
+                .iter()
+                .map(|column| Cell::new(console::style(column.header()).bold())),
+        );
+    }
+
+    // instances right under their template only show the instance
+    let templates: HashSet<(DaemonType, &str)> = rows
+        .iter()
+        .filter(|(unit, _)| unit.info.is_template())
+        .map(|(unit, _)| (unit.daemon, unit.info.name.as_str()))
+        .collect();
+
+    let mut longest = 0;
+    for (unit, details) in rows {
+        let grouped = !options.sorted
+            && !unit.info.is_template()
+            && unit
+                .info
+                .template()
+                .is_some_and(|template| templates.contains(&(unit.daemon, template.as_str())));
+        table.add_row(columns.iter().map(|column| match grouped {
+            true => column.render_instance(unit, details),
+            false => column.render(unit, details),
+        }));
+
+        let mut l = console::measure_text_width(&unit.info.name);
+        if options.verbose >= 2 {
+            l += console::measure_text_width(&unit.info.desc);
+        }
+        longest = longest.max(l);
+    }
+
+    let mut hide = |column: Column| {
+        // only the default columns are picked automatically
+        if !options.picked {
+            let i = columns.iter().position(|c| *c == column).unwrap();
+            table
+                .column_mut(i)
+                .unwrap()
+                .set_constraint(ColumnConstraint::Hidden);
+        }
+    };
+
+    let width = console::Term::stdout().size_checked().unwrap_or((0, 200)).1 as usize;
+    let abbreviate = (longest + 40) > width || options.verbose < 3;
+    let abbreviate = abbreviate && !options.no_abbr;
+
+    // if daemon specified remove daemon column
+    if !options.several_daemons {
+        hide(Column::Daemon);
+    }
+
+    if options.verbose == 0 || !abbreviate {
+        hide(Column::States);
+    }
+
+    if options.types.is_empty() {
+        hide(Column::Type);
+        hide(Column::BaseName);
+    } else if options.types.len() == 1 {
+        hide(Column::Type);
+        hide(Column::Name);
+    } else {
+        hide(Column::Name);
+    }
+
+    if options.verbose == 0 || abbreviate {
+        hide(Column::Load);
+        hide(Column::Active);
+        hide(Column::Sub);
+    }
+
+    if options.verbose == 0 {
+        hide(Column::UnitFileState);
+    }
+
+    if options.verbose < 2 {
+        hide(Column::Description);
+    }
+
+    //TODO:
+    // if type specified remove type from name,
+    // if type not specified or only 1 type, remove type column
+    // if status specified remove status column
+    // if active specified remove active column
+    // if loaded specified remove loaded column
+
+    // if wraps, and not verbose remove desc
+    // if still wraps swap with abbreviated
+
+    table
+}
@@ -0,0 +1,670 @@
+//! `sys tui`: the units of every daemon on one screen, kept current from D-Bus signals.
+//!
+//! The list is what `sys` would print for the filter in the bar, coloured the same way, and
+//! the keys are the command line's flags: S, K and R queue start, stop and restart jobs, 1
+//! and 0 enable and disable, L opens the journal of the units. They act on the marked units,
+//! or on the one under the cursor if none are marked. Signals only mark the list stale, it is
+//! listed again at most every [`REFRESH`], so a burst of changes costs one ListUnits.
+use std::{
+    collections::{
+        BTreeSet,
+        HashMap,
+    },
+    io::{
+        self,
+        Write,
+    },
+    path::PathBuf,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use crossterm::{
+    cursor,
+    event::{
+        Event as TermEvent,
+        EventStream,
+        KeyCode,
+        KeyEvent,
+        KeyEventKind,
+        KeyModifiers,
+    },
+    queue,
+    style::Print,
+    terminal::{
+        self,
+        ClearType,
+    },
+};
+use futures::{
+    future,
+    stream::{
+        LocalBoxStream,
+        SelectAll,
+    },
+    StreamExt,
+};
+use itertools::Itertools;
+use tokio::task::{
+    JoinError,
+    JoinHandle,
+};
+use zbus::{
+    Connection,
+    MatchRule,
+    MessageStream,
+    MessageType,
+};
+
+use crate::{
+    columns::{
+        Column,
+        Details,
+    },
+    journal::{
+        self,
+        JournalFollower,
+    },
+    status::{
+        format_journal_entry,
+        journal_units,
+        read_unit_journal,
+    },
+    Daemon,
+    DaemonType,
+    Filter,
+    Unit,
+    UnitAction,
+};
+
+/// the least time between two listings
+pub const REFRESH: Duration = Duration::from_millis(500);
+
+/// journal lines the pane keeps
+const JOURNAL_LINES: usize = 500;
+
+const HELP: &str =
+    "space mark  S start  K stop  R restart  1 enable  0 disable  L journal  / filter  q quit";
+
+type Key = (DaemonType, String);
+
+fn key(unit: &Unit) -> Key {
+    (unit.daemon, unit.info.name.clone())
+}
+
+enum Signal {
+    /// something about the units changed, list them again
+    Stale,
+    JobRemoved {
+        daemon: DaemonType,
+        unit: String,
+        result: String,
+    },
+}
+
+async fn daemon_signals<'a>(
+    daemon: &Daemon<'a>,
+    conn: &Connection,
+) -> zbus::Result<Vec<LocalBoxStream<'a, Signal>>> {
+    let manager = &daemon.manager;
+    let typ = daemon.typ;
+    let unit_new = manager.receive_unit_new().await?;
+    let unit_removed = manager.receive_unit_removed().await?;
+    let unit_files = manager.receive_unit_files_changed().await?;
+    let job_removed = manager
+        .receive_job_removed()
+        .await?
+        .filter_map(move |signal| async move {
+            let args = signal.args().ok()?;
+            Some(Signal::JobRemoved {
+                daemon: typ,
+                unit: args.unit().to_string(),
+                result: args.result().to_string(),
+            })
+        });
+
+    // one match rule for the states of every unit, instead of one per unit like --follow
+    let rule = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .interface("org.freedesktop.DBus.Properties")?
+        .member("PropertiesChanged")?
+        .path_namespace("/org/freedesktop/systemd1/unit")?
+        .build();
+    let states = MessageStream::for_match_rule(rule, conn, None).await?;
+
+    Ok(vec![
+        unit_new.map(|_| Signal::Stale).boxed_local(),
+        unit_removed.map(|_| Signal::Stale).boxed_local(),
+        unit_files.map(|_| Signal::Stale).boxed_local(),
+        states.map(|_| Signal::Stale).boxed_local(),
+        job_removed.boxed_local(),
+    ])
+}
+
+/// raw mode on the alternate screen, until dropped
+struct Screen;
+
+impl Screen {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        crossterm::execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(Screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = crossterm::execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+struct Journal<'a> {
+    units: HashMap<Key, Unit<'a>>,
+    lines: Vec<String>,
+    follower: JournalFollower,
+    /// what is already in the files, read off the event loop
+    reading: Option<JoinHandle<Vec<journal::Entry>>>,
+    /// the last seqnum read of each seqnum id, the follower may see the entries written while
+    /// reading again
+    read_until: HashMap<[u8; 16], u64>,
+}
+
+struct Tui<'a, P> {
+    daemons: Vec<Daemon<'a>>,
+    filter: Filter,
+    /// the words `filter` came from
+    filter_text: String,
+    /// what is typed into the bar, while it is
+    editing: Option<String>,
+    parse_filter: P,
+    units: Vec<Unit<'a>>,
+    marked: BTreeSet<Key>,
+    cursor: usize,
+    /// the first unit on screen
+    scroll: usize,
+    journal: Option<Journal<'a>>,
+    journal_dirs: Vec<PathBuf>,
+    /// the jobs we queued, to say how they went
+    jobs: HashMap<Key, UnitAction>,
+    message: String,
+    stale: bool,
+    listed: Instant,
+}
+
+impl<'a, P: Fn(&str) -> Result<Filter, String>> Tui<'a, P> {
+    async fn list(&mut self) {
+        let mut units = Vec::new();
+        let mut errors = Vec::new();
+        for daemon in &self.daemons {
+            match daemon.units(&self.filter).await {
+                Ok((listed, unit_errors)) => {
+                    units.extend(listed);
+                    errors.extend(
+                        unit_errors
+                            .into_iter()
+                            .map(|(name, e)| format!("{name}: {e}")),
+                    );
+                }
+                Err(e) => errors.push(format!("listing {} units: {e}", daemon.typ)),
+            }
+        }
+        units.sort_by(|a, b| (a.daemon, &a.info.name).cmp(&(b.daemon, &b.info.name)));
+
+        // the cursor stays on its unit while it is there
+        let at = self.units.get(self.cursor).map(key);
+        self.units = units;
+        self.cursor = at
+            .and_then(|at| self.units.iter().position(|unit| key(unit) == at))
+            .unwrap_or(self.cursor)
+            .min(self.units.len().saturating_sub(1));
+        if let Some(e) = errors.into_iter().next() {
+            self.message = e;
+        }
+        self.stale = false;
+        self.listed = Instant::now();
+    }
+
+    /// the marked units, or the one under the cursor
+    fn targets(&self) -> Vec<usize> {
+        match self.marked.is_empty() {
+            true => (self.cursor < self.units.len())
+                .then_some(self.cursor)
+                .into_iter()
+                .collect(),
+            false => (0..self.units.len())
+                .filter(|i| self.marked.contains(&key(&self.units[*i])))
+                .collect(),
+        }
+    }
+
+    /// on every target, the ones that fail are told about with the rest
+    async fn queue_jobs(&mut self, action: UnitAction) {
+        let mut queued = Vec::new();
+        let mut errors = Vec::new();
+        for i in self.targets() {
+            let unit = &mut self.units[i];
+            let job = match unit.load().await {
+                Ok(()) => unit.queue_job(action).await,
+                Err(e) => Err(e),
+            };
+            match job {
+                Ok(_) => {
+                    self.jobs.insert(key(unit), action);
+                    queued.push(unit.info.name.clone());
+                }
+                Err(e) => errors.push(format!("{action} {}: {e}", unit.info.name)),
+            }
+        }
+        if !queued.is_empty() {
+            errors.insert(0, format!("{action} {}", queued.join(", ")));
+        }
+        self.message = errors.join("; ");
+    }
+
+    async fn unit_files(&mut self, enable: bool) {
+        let mut changed = 0;
+        let mut errors = Vec::new();
+        for i in self.targets() {
+            let unit = &self.units[i];
+            let (action, changes) = match enable {
+                true => ("enable", unit.enable(false, false).await.map(|(_, c)| c)),
+                false => ("disable", unit.disable(false).await),
+            };
+            match changes {
+                Ok(changes) => changed += changes.len(),
+                Err(e) => errors.push(format!("{action} {}: {e}", unit.info.name)),
+            }
+        }
+        errors.insert(0, format!("{changed} unit file links changed"));
+        self.message = errors.join("; ");
+        self.stale = true;
+    }
+
+    fn toggle_journal(&mut self) {
+        if self.journal.take().is_some() {
+            return;
+        }
+        let units: HashMap<Key, Unit<'a>> = self
+            .targets()
+            .into_iter()
+            .map(|i| (key(&self.units[i]), self.units[i].clone()))
+            .collect();
+        if units.is_empty() {
+            return;
+        }
+
+        // from before reading, so nothing falls between the two
+        let follower = JournalFollower::new(&self.journal_dirs);
+        let names = units.keys().cloned().collect();
+        let dirs = self.journal_dirs.clone();
+        let reading = tokio::task::spawn_blocking(move || read_unit_journal(&names, &dirs));
+
+        self.journal = Some(Journal {
+            units,
+            lines: vec![console::style("reading the journal").dim().to_string()],
+            follower,
+            reading: Some(reading),
+            read_until: HashMap::new(),
+        });
+    }
+
+    /// the entries of the journal pane once they are read, pending until then
+    async fn journal_read(&mut self) -> Result<Vec<journal::Entry>, JoinError> {
+        let Some(reading) = self.journal.as_mut().and_then(|j| j.reading.as_mut()) else {
+            return future::pending().await;
+        };
+        let entries = reading.await;
+        if let Some(journal) = &mut self.journal {
+            journal.reading = None;
+        }
+        entries
+    }
+
+    fn fill_journal(&mut self, entries: Result<Vec<journal::Entry>, JoinError>) {
+        let Some(journal) = &mut self.journal else {
+            return;
+        };
+        let entries = match entries {
+            Ok(entries) => entries,
+            Err(e) => {
+                journal.lines.clear();
+                self.message = format!("reading the journal: {e}");
+                return;
+            }
+        };
+        for entry in &entries {
+            let seqnum = journal.read_until.entry(entry.seqnum_id).or_default();
+            *seqnum = entry.seqnum.max(*seqnum);
+        }
+        journal.lines = entries[entries.len().saturating_sub(JOURNAL_LINES)..]
+            .iter()
+            .filter_map(|entry| {
+                let unit = journal_units(entry)
+                    .find_map(|(daemon, name)| journal.units.get(&(daemon, name.to_string())))?;
+                Some(format_journal_entry(entry, unit))
+            })
+            .collect();
+    }
+
+    /// true if there is something new to show
+    fn poll_journal(&mut self) -> bool {
+        let Some(journal) = &mut self.journal else {
+            return false;
+        };
+        if journal.reading.is_some() {
+            return false;
+        }
+        let mut new = false;
+        for entry in journal.follower.poll() {
+            let read = journal.read_until.get(&entry.seqnum_id);
+            if read.is_some_and(|seqnum| entry.seqnum <= *seqnum) {
+                continue;
+            }
+            let unit = journal_units(&entry)
+                .find_map(|(daemon, name)| journal.units.get(&(daemon, name.to_string())));
+            if let Some(unit) = unit {
+                journal.lines.push(format_journal_entry(&entry, unit));
+                new = true;
+            }
+        }
+        let over = journal.lines.len().saturating_sub(JOURNAL_LINES);
+        journal.lines.drain(..over);
+        new
+    }
+
+    /// true if there is something new to show
+    fn signal(&mut self, signal: Signal) -> bool {
+        self.stale = true;
+        let Signal::JobRemoved {
+            daemon,
+            unit,
+            result,
+        } = signal
+        else {
+            return false;
+        };
+        let Some(action) = self.jobs.remove(&(daemon, unit.clone())) else {
+            return false;
+        };
+        let result = match result.as_str() {
+            "done" | "skipped" => console::style(result).green(),
+            _ => console::style(result).red(),
+        };
+        self.message = format!("{action} {unit}: {result}");
+        true
+    }
+
+    async fn apply_filter(&mut self) {
+        let text = self.editing.clone().unwrap_or_default();
+        match (self.parse_filter)(&text) {
+            Ok(filter) => {
+                self.filter = filter;
+                self.filter_text = text;
+                self.editing = None;
+                self.marked.clear();
+                self.message.clear();
+                self.list().await;
+            }
+            Err(e) => self.message = e,
+        }
+    }
+
+    /// false once the user is done
+    async fn key(&mut self, event: KeyEvent) -> bool {
+        if event.code == KeyCode::Char('c') && event.modifiers.contains(KeyModifiers::CONTROL) {
+            return false;
+        }
+
+        if let Some(text) = &mut self.editing {
+            match event.code {
+                KeyCode::Esc => self.editing = None,
+                KeyCode::Enter => self.apply_filter().await,
+                KeyCode::Backspace => {
+                    text.pop();
+                }
+                KeyCode::Char(c) => text.push(c),
+                _ => {}
+            }
+            return true;
+        }
+
+        let last = self.units.len().saturating_sub(1);
+        let page = terminal::size().map_or(10, |(_, h)| h as usize / 2);
+        match event.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.cursor = (self.cursor + 1).min(last),
+            KeyCode::PageUp => self.cursor = self.cursor.saturating_sub(page),
+            KeyCode::PageDown => self.cursor = (self.cursor + page).min(last),
+            KeyCode::Home | KeyCode::Char('g') => self.cursor = 0,
+            KeyCode::End | KeyCode::Char('G') => self.cursor = last,
+            KeyCode::Char(' ') => {
+                if let Some(unit) = self.units.get(self.cursor) {
+                    let unit = key(unit);
+                    if !self.marked.remove(&unit) {
+                        self.marked.insert(unit);
+                    }
+                    self.cursor = (self.cursor + 1).min(last);
+                }
+            }
+            KeyCode::Char('S') => self.queue_jobs(UnitAction::Start).await,
+            KeyCode::Char('K') => self.queue_jobs(UnitAction::Stop).await,
+            KeyCode::Char('R') => self.queue_jobs(UnitAction::Restart).await,
+            KeyCode::Char('1') => self.unit_files(true).await,
+            KeyCode::Char('0') => self.unit_files(false).await,
+            KeyCode::Char('L') => self.toggle_journal(),
+            KeyCode::Char('/') => self.editing = Some(self.filter_text.clone()),
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+        let mut lines = Vec::with_capacity(height);
+
+        let text = self.editing.as_ref().unwrap_or(&self.filter_text);
+        lines.push(format!("{} {text}", console::style("filter").bold()));
+
+        // the bar and the status line, and half of the rest for the journal
+        let rows = height.saturating_sub(2);
+        let journal_rows = match self.journal {
+            Some(_) => rows / 2,
+            None => 0,
+        };
+        let unit_rows = rows - journal_rows;
+
+        let mut columns = vec![
+            Column::Name,
+            Column::Active,
+            Column::Sub,
+            Column::UnitFileState,
+            Column::Description,
+        ];
+        if self.daemons.len() > 1 {
+            columns.insert(0, Column::Daemon);
+        }
+        let details = Details::default();
+        let cells = self
+            .units
+            .iter()
+            .map(|unit| {
+                columns
+                    .iter()
+                    .map(|column| column.render(unit, &details))
+                    .collect_vec()
+            })
+            .collect_vec();
+        let widths = (0..columns.len())
+            .map(|i| {
+                cells
+                    .iter()
+                    .map(|row| console::measure_text_width(&row[i]))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect_vec();
+
+        if self.cursor < self.scroll {
+            self.scroll = self.cursor;
+        } else if unit_rows > 0 && self.cursor >= self.scroll + unit_rows {
+            self.scroll = self.cursor + 1 - unit_rows;
+        }
+        for i in self.scroll..self.scroll + unit_rows {
+            let Some(row) = cells.get(i) else {
+                lines.push(String::new());
+                continue;
+            };
+            let mark = match self.marked.contains(&key(&self.units[i])) {
+                true => "●",
+                false => " ",
+            };
+            let cursor = match i == self.cursor {
+                true => console::style(">").bold().to_string(),
+                false => " ".to_string(),
+            };
+            let row = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| {
+                    let pad = width - console::measure_text_width(cell);
+                    format!("{cell}{}", " ".repeat(pad))
+                })
+                .join("  ");
+            lines.push(format!("{cursor}{mark} {row}"));
+        }
+
+        if let Some(journal) = &self.journal {
+            let names = journal
+                .units
+                .keys()
+                .map(|(_, name)| name)
+                .sorted()
+                .join(", ");
+            let title = format!("── journal of {names} ");
+            let rule = "─".repeat(width.saturating_sub(console::measure_text_width(&title)));
+            lines.push(console::style(format!("{title}{rule}")).dim().to_string());
+            let shown = journal_rows.saturating_sub(1);
+            let skip = journal.lines.len().saturating_sub(shown);
+            lines.extend(journal.lines[skip..].iter().cloned());
+            lines.resize(1 + unit_rows + journal_rows, String::new());
+        }
+
+        let status = match self.message.is_empty() {
+            true => console::style(HELP).dim().to_string(),
+            false => self.message.clone(),
+        };
+        lines.push(format!(
+            "{}  {}",
+            console::style(format!("{}/{}", self.marked.len(), self.units.len())).dim(),
+            status
+        ));
+
+        for (row, line) in lines.iter().enumerate().take(height) {
+            queue!(
+                out,
+                cursor::MoveTo(0, row as u16),
+                Print(console::truncate_str(line, width, "")),
+                terminal::Clear(ClearType::UntilNewLine)
+            )?;
+        }
+        match &self.editing {
+            Some(text) => {
+                let column = console::measure_text_width(&format!("filter {text}"));
+                queue!(out, cursor::MoveTo(column as u16, 0), cursor::Show)?;
+            }
+            None => queue!(out, cursor::Hide)?,
+        }
+        out.flush()
+    }
+}
+
+/// Run the dashboard until the user quits, on the terminal stdout is.
+///
+/// `filter_text` is what `filter` was parsed from, `parse_filter` parses what is typed into
+/// the filter bar the same way.
+pub async fn run(
+    conns: &[(DaemonType, Connection)],
+    filter: Filter,
+    filter_text: String,
+    parse_filter: impl Fn(&str) -> Result<Filter, String>,
+    journal_dirs: &[PathBuf],
+) -> io::Result<()> {
+    let mut signals: SelectAll<LocalBoxStream<Signal>> = SelectAll::new();
+    let mut daemons = Vec::new();
+    let mut message = String::new();
+    for (typ, conn) in conns {
+        let daemon = match Daemon::new(*typ, conn).await {
+            Ok(daemon) => daemon,
+            Err(e) => {
+                message = format!("{typ} daemon: {e}");
+                continue;
+            }
+        };
+        match daemon_signals(&daemon, conn).await {
+            Ok(streams) => signals.extend(streams),
+            Err(e) => message = format!("not following {typ} daemon: {e}"),
+        }
+        daemons.push(daemon);
+    }
+
+    let mut tui = Tui {
+        daemons,
+        filter,
+        filter_text,
+        editing: None,
+        parse_filter,
+        units: Vec::new(),
+        marked: BTreeSet::new(),
+        cursor: 0,
+        scroll: 0,
+        journal: None,
+        journal_dirs: journal_dirs.to_vec(),
+        jobs: HashMap::new(),
+        message,
+        stale: false,
+        listed: Instant::now(),
+    };
+    tui.list().await;
+
+    let _screen = Screen::enter()?;
+    let mut stdout = io::stdout();
+    let mut keys = EventStream::new();
+    let mut tick = tokio::time::interval(Duration::from_millis(250));
+    let mut redraw = true;
+    loop {
+        if redraw {
+            tui.draw(&mut stdout)?;
+        }
+        redraw = tokio::select! {
+            event = keys.next() => match event {
+                Some(Ok(TermEvent::Key(event))) if event.kind != KeyEventKind::Release => {
+                    if !tui.key(event).await {
+                        break;
+                    }
+                    true
+                }
+                Some(Ok(_)) => true,
+                Some(Err(e)) => return Err(e),
+                None => break,
+            },
+            Some(signal) = signals.next() => tui.signal(signal),
+            entries = tui.journal_read() => {
+                tui.fill_journal(entries);
+                true
+            }
+            _ = tick.tick() => {
+                let listed = tui.stale && tui.listed.elapsed() >= REFRESH;
+                if listed {
+                    tui.list().await;
+                }
+                tui.poll_journal() || listed
+            }
+        };
+    }
+    Ok(())
+}
@@ -0,0 +1,714 @@
+//! Units as systemd lists them, and the jobs we run on them.
+use std::{
+    fmt::Display,
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use console::StyledObject;
+use futures::StreamExt;
+use regex::Regex;
+use tracing::{
+    info,
+    span,
+    warn,
+    Instrument,
+    Level,
+};
+use zbus::zvariant::OwnedObjectPath;
+use zbus_systemd::systemd1::{
+    ManagerProxy,
+    UnitProxy,
+};
+
+use crate::{
+    error::SysError,
+    journal::JournalFollower,
+    properties::{
+        Properties,
+        UNIT_INTERFACE,
+    },
+    state::{
+        ActiveState,
+        LoadState,
+        State,
+        SubState,
+    },
+    status::journal_units,
+};
+
+/// unit types, see systemd.unit(5)
+#[derive(
+    Debug, ValueEnum, Clone, strum::EnumString, strum::IntoStaticStr, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[strum(serialize_all = "lowercase")]
+#[strum(ascii_case_insensitive)]
+pub enum TypeOpt {
+    #[clap(name = ".")]
+    _Any,
+
+    Socket,
+    #[clap(alias = "s")]
+    Service,
+    Device,
+    Timer,
+    Target,
+    Slice,
+    Scope,
+    Mount,
+    Automount,
+    Swap,
+    Path,
+
+    /// whatever a newer systemd comes up with
+    #[clap(skip)]
+    #[strum(default)]
+    Other(String),
+}
+
+impl Display for TypeOpt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeOpt::Other(typ) => f.pad(typ),
+            typ => f.pad(typ.into()),
+        }
+    }
+}
+
+impl serde::Serialize for TypeOpt {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl TypeOpt {
+    pub fn color_str(&self, with_dot: bool) -> StyledObject<String> {
+        let unit_type_str = console::style(format!("{}{}", if with_dot { "." } else { "" }, self));
+        match self {
+            TypeOpt::Socket => unit_type_str.yellow(),
+            TypeOpt::Service => unit_type_str.white().bold(),
+            TypeOpt::Device => unit_type_str.blue(),
+            TypeOpt::Timer => unit_type_str.green(),
+            TypeOpt::Target => unit_type_str.magenta().bright(),
+            TypeOpt::Slice => unit_type_str.yellow().dim(),
+            TypeOpt::Scope => unit_type_str.dim(),
+            TypeOpt::Mount => unit_type_str.magenta().dim(),
+            TypeOpt::Automount => unit_type_str.magenta(),
+            TypeOpt::Swap => unit_type_str.cyan().dim(),
+            TypeOpt::Path => unit_type_str.blue().dim(),
+            _ => unit_type_str,
+        }
+    }
+
+    pub fn variants() -> [Self; 11] {
+        [
+            TypeOpt::Socket,
+            TypeOpt::Service,
+            TypeOpt::Device,
+            TypeOpt::Timer,
+            TypeOpt::Target,
+            TypeOpt::Slice,
+            TypeOpt::Scope,
+            TypeOpt::Mount,
+            TypeOpt::Automount,
+            TypeOpt::Swap,
+            TypeOpt::Path,
+        ]
+    }
+}
+
+/// whether and how a unit is enabled, from ListUnitFiles
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    strum::EnumString,
+    strum::Display,
+    serde::Serialize,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+)]
+#[strum(serialize_all = "kebab-case")]
+#[strum(ascii_case_insensitive)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnitFileStatus {
+    Linked,
+    LinkedRuntime,
+    Transient,
+    Masked,
+    MaskedRuntime,
+    Generated,
+    EnabledRuntime,
+    Enabled,
+    Disabled,
+    Alias,
+    Static,
+    Indirect,
+    Bad,
+}
+
+#[derive(Debug, Clone)]
+pub struct UnitFile {
+    pub path: PathBuf,
+    pub name: String,
+    pub typ: TypeOpt,
+    pub status: UnitFileStatus,
+}
+impl UnitFile {
+    pub fn new((path, status): (String, String)) -> Result<Self, SysError> {
+        let status = UnitFileStatus::from_str(&status)
+            .map_err(|_| SysError::parse("unit file status", &status))?;
+        let path = PathBuf::from(path);
+        let name = unit_file_name(&path)
+            .ok_or_else(|| SysError::parse("unit file path", path.to_string_lossy()))?
+            .to_string();
+        let (_, typ) = split_unit_name(&name)?;
+
+        Ok(UnitFile {
+            status,
+            path,
+            name,
+            typ,
+        })
+    }
+}
+
+pub fn unit_file_name(path: &std::path::Path) -> Option<&str> {
+    path.file_name()?.to_str()
+}
+
+#[derive(Debug, Clone)]
+pub struct ListUnitsItem {
+    pub name: String,               //0
+    pub desc: String,               //1
+    pub loaded: State<LoadState>,   //2
+    pub active: State<ActiveState>, //3
+    pub status: State<SubState>,    //4
+    other_name: String,
+    pub path: OwnedObjectPath,
+    idk: u32,
+    idk2: String,
+    idk3: OwnedObjectPath,
+    pub unit_type: TypeOpt,
+    pub base_name: String,
+    /// None if there is no unit file, or nobody looked it up
+    pub unit_file_state: Option<UnitFileStatus>,
+}
+
+lazy_static::lazy_static! {
+    static ref TYPE_REGEX : Regex = Regex::new(r"(.*)\.([^.]*)$").unwrap();
+}
+
+/// "foo.service" into ("foo", Service)
+pub fn split_unit_name(name: &str) -> Result<(String, TypeOpt), SysError> {
+    let m = TYPE_REGEX
+        .captures(name)
+        .ok_or_else(|| SysError::parse("unit name", name))?;
+    let base_name = m[1].to_string();
+    let unit_type =
+        <TypeOpt as FromStr>::from_str(&m[2]).map_err(|_| SysError::parse("unit type", &m[2]))?;
+    Ok((base_name, unit_type))
+}
+
+/// "foo@bar" into ("foo", Some("bar")), a template like "foo@" has the empty instance
+pub fn split_instance(base_name: &str) -> (&str, Option<&str>) {
+    match base_name.split_once('@') {
+        Some((prefix, instance)) => (prefix, Some(instance)),
+        None => (base_name, None),
+    }
+}
+
+impl ListUnitsItem {
+    /// a template like foo@.service, that units like foo@bar.service are made from
+    pub fn is_template(&self) -> bool {
+        split_instance(&self.base_name).1 == Some("")
+    }
+
+    /// "bar" of foo@bar.service, None for templates and units that are no instance
+    pub fn instance(&self) -> Option<&str> {
+        split_instance(&self.base_name)
+            .1
+            .filter(|instance| !instance.is_empty())
+    }
+
+    /// foo@.service for foo@bar.service, and for foo@.service itself
+    pub fn template(&self) -> Option<String> {
+        let (prefix, instance) = split_instance(&self.base_name);
+        instance.map(|_| format!("{prefix}@.{}", self.unit_type))
+    }
+
+    /// an instance of this template, systemd makes it once someone LoadUnits the name
+    pub fn instantiate(&self, instance: &str) -> Self {
+        let (prefix, _) = split_instance(&self.base_name);
+        let base_name = format!("{prefix}@{instance}");
+        Self {
+            name: format!("{base_name}.{}", self.unit_type),
+            desc: Default::default(),
+            loaded: State::Known(LoadState::NotLoaded),
+            active: State::Known(ActiveState::Inactive),
+            status: SubState::parse(&self.unit_type, "dead"),
+            path: Default::default(),
+            base_name,
+            // the template's links are not the instance's
+            unit_file_state: None,
+            ..self.clone()
+        }
+    }
+
+    /// what we know of a unit file without loading it, a unit systemd has not loaded is
+    /// inactive and dead
+    pub fn unloaded(unit_file: &UnitFile) -> Self {
+        let unit_type = unit_file.typ.clone();
+        let base_name = match unit_file.name.rsplit_once('.') {
+            Some((base_name, _)) => base_name.to_string(),
+            None => unit_file.name.clone(),
+        };
+        Self {
+            name: unit_file.name.clone(),
+            desc: Default::default(),
+            loaded: State::Known(LoadState::NotLoaded),
+            active: State::Known(ActiveState::Inactive),
+            status: SubState::parse(&unit_type, "dead"),
+            other_name: Default::default(),
+            path: Default::default(),
+            idk: Default::default(),
+            idk2: Default::default(),
+            idk3: Default::default(),
+            unit_type,
+            base_name,
+            unit_file_state: Some(unit_file.status),
+        }
+    }
+
+    /// with a single GetAll, not cached since states change
+    pub async fn from_proxy(proxy: &UnitProxy<'_>) -> Result<Self, SysError> {
+        let properties = Properties::get_all(proxy.inner(), UNIT_INTERFACE).await?;
+        let mut info = Self::from_properties(&properties)?;
+        info.path = proxy.path().to_owned().into();
+        Ok(info)
+    }
+
+    /// from the properties of the org.freedesktop.systemd1.Unit interface, without the
+    /// object path since that is not one of them
+    pub fn from_properties(properties: &Properties) -> Result<Self, SysError> {
+        let get = |name: &'static str| {
+            properties
+                .get::<String>(name)
+                .ok_or_else(|| SysError::parse("unit property", name))
+        };
+        let name = properties
+            .get::<Vec<String>>("Names")
+            .unwrap_or_default()
+            .first()
+            .cloned()
+            .ok_or_else(|| SysError::parse("unit names", "[]"))?;
+        let (base_name, unit_type) = split_unit_name(&name)?;
+
+        Ok(Self {
+            name,
+            desc: get("Description")?,
+            loaded: get("LoadState")?.as_str().into(),
+            active: get("ActiveState")?.as_str().into(),
+            status: SubState::parse(&unit_type, &get("SubState")?),
+            other_name: Default::default(),
+            path: Default::default(),
+            idk: Default::default(),
+            idk2: Default::default(),
+            idk3: Default::default(),
+            unit_type,
+            base_name,
+            unit_file_state: None,
+        })
+    }
+}
+
+impl
+    TryFrom<(
+        String,
+        String,
+        String,
+        String,
+        String,
+        String,
+        OwnedObjectPath,
+        u32,
+        String,
+        OwnedObjectPath,
+    )> for ListUnitsItem
+{
+    type Error = SysError;
+
+    fn try_from(
+        t: (
+            String,
+            String,
+            String,
+            String,
+            String,
+            String,
+            OwnedObjectPath,
+            u32,
+            String,
+            OwnedObjectPath,
+        ),
+    ) -> Result<Self, SysError> {
+        let (base_name, unit_type) = split_unit_name(&t.0)?;
+
+        let loaded = t.2.as_str().into();
+        let active = t.3.as_str().into();
+        let status = SubState::parse(&unit_type, &t.4);
+
+        Ok(ListUnitsItem {
+            name: t.0,
+            desc: t.1,
+            loaded,
+            active,
+            status,
+            other_name: t.5,
+            path: t.6,
+            idk: t.7,
+            idk2: t.8,
+            idk3: t.9,
+            unit_type,
+            base_name,
+            unit_file_state: None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DaemonType {
+    /// the manager of the user with this uid
+    User(u32),
+    System,
+}
+
+impl Display for DaemonType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DaemonType::System => write!(f, "SYSTEM"),
+            // only name the user when it isn't us
+            DaemonType::User(uid) if *uid == users::get_current_uid() => write!(f, "USER"),
+            DaemonType::User(uid) => write!(f, "USER({})", user_name(*uid)),
+        }
+    }
+}
+
+pub fn user_name(uid: u32) -> String {
+    users::get_user_by_uid(uid)
+        .map(|user| user.name().to_string_lossy().into_owned())
+        .unwrap_or_else(|| uid.to_string())
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone)]
+pub struct Unit<'a> {
+    pub info: ListUnitsItem,
+    pub daemon: DaemonType,
+    //conn : &'a Connection,
+    pub manager: ManagerProxy<'a>,
+    /// None for a unit file nobody loaded yet, see [`Unit::load`]
+    pub proxy: Option<UnitProxy<'a>>,
+}
+
+#[derive(Debug, Clone, Copy, strum::Display, serde::Serialize, PartialEq, Eq)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum UnitAction {
+    Start,
+    Stop,
+    Restart,
+}
+
+/// result string of the JobRemoved signal
+/// https://www.freedesktop.org/wiki/Software/systemd/dbus/
+#[derive(Debug, Clone, strum::EnumString, strum::IntoStaticStr, PartialEq, Eq)]
+#[strum(serialize_all = "lowercase")]
+pub enum JobResult {
+    Done,
+    Canceled,
+    Timeout,
+    Failed,
+    Dependency,
+    Skipped,
+    Invalid,
+    Assert,
+    Unsupported,
+    Collected,
+    Once,
+    Frozen,
+    Concurrency,
+
+    /// whatever a newer systemd comes up with, counted as failed
+    #[strum(default)]
+    Other(String),
+}
+
+impl Display for JobResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobResult::Other(result) => f.pad(result),
+            result => f.pad(result.into()),
+        }
+    }
+}
+
+impl serde::Serialize for JobResult {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl JobResult {
+    pub fn is_success(&self) -> bool {
+        matches!(self, JobResult::Done | JobResult::Skipped)
+    }
+}
+
+impl<'a> Unit<'a> {
+    /// the unit systemd has loaded, for what needs more than the unit file
+    pub fn proxy(&self) -> Result<&UnitProxy<'a>, SysError> {
+        self.proxy
+            .as_ref()
+            .ok_or_else(|| SysError::NotFound(format!("{} is not loaded", self.info.name)))
+    }
+
+    /// LoadUnit a unit file nobody loaded yet, and read the states it has now
+    pub async fn load(&mut self) -> Result<(), SysError> {
+        *self = self.loaded().await?;
+        Ok(())
+    }
+
+    /// the proxy of a unit ListUnits gave, at the path it gave
+    pub async fn listed_proxy(&self) -> Result<UnitProxy<'a>, SysError> {
+        Ok(UnitProxy::builder(self.manager.inner().connection())
+            .path(self.info.path.clone())?
+            .build()
+            .await?)
+    }
+
+    /// the unit as [`Unit::load`] leaves it, for loading many behind shared references
+    pub async fn loaded(&self) -> Result<Unit<'a>, SysError> {
+        if self.proxy.is_some() {
+            return Ok(self.clone());
+        }
+        let path = self.manager.load_unit(self.info.name.clone()).await?;
+        let proxy = UnitProxy::builder(self.manager.inner().connection())
+            .path(path)?
+            .build()
+            .await?;
+        let mut info = ListUnitsItem::from_proxy(&proxy).await?;
+        info.unit_file_state = self.info.unit_file_state;
+        Ok(Unit {
+            info,
+            proxy: Some(proxy),
+            ..self.clone()
+        })
+    }
+
+    /// queue a job without waiting for it, returns the job's object path
+    pub async fn queue_job(&self, action: UnitAction) -> Result<OwnedObjectPath, SysError> {
+        // default job mode https://www.freedesktop.org/software/systemd/man/systemctl.html#--job-mode=
+        let mode = "replace".to_string();
+        let proxy = self.proxy()?;
+        let job = match action {
+            UnitAction::Start => proxy.start(mode).await,
+            UnitAction::Stop => proxy.stop(mode).await,
+            UnitAction::Restart => proxy.restart(mode).await,
+        }?;
+        Ok(job)
+    }
+
+    /// queue a job and wait for systemd to remove it, logging state changes and journal
+    /// entries of the unit in child spans meanwhile
+    pub async fn run_job(
+        &self,
+        action: UnitAction,
+        journal: &mut JournalFollower,
+    ) -> Result<JobResult, SysError> {
+        let span = span!(
+            Level::INFO,
+            "unit",
+            daemon = self.daemon.to_string(),
+            name = self.info.name
+        );
+
+        let job = async {
+            info!("request {action}");
+            // subscribe before queueing the job, otherwise a fast job can be removed before we listen
+            let mut removed = self.manager.receive_job_removed().await?;
+
+            let job = self.queue_job(action).await?;
+            info!("pending");
+            let proxy = self.proxy()?;
+
+            let wait = async {
+                loop {
+                    let Some(signal) = removed.next().await else {
+                        return Err(SysError::DBus(zbus::Error::Failure(format!(
+                            "lost JobRemoved signal stream for {}",
+                            job.as_str()
+                        ))));
+                    };
+                    let args = signal.args()?;
+                    if *args.job() == job {
+                        let result = args.result();
+                        return result
+                            .parse::<JobResult>()
+                            .map_err(|_| SysError::parse("job result", result.to_string()));
+                    }
+                }
+            };
+
+            let state_span = span!(Level::INFO, "state");
+            let journal_span = span!(Level::INFO, "journal");
+            let watch = async {
+                let mut active = proxy.receive_active_state_changed().await;
+                let mut sub = proxy.receive_sub_state_changed().await;
+                let mut tick = tokio::time::interval(Duration::from_millis(250));
+                loop {
+                    tokio::select! {
+                        Some(change) = active.next() => if let Ok(state) = change.get().await {
+                            info!(parent: &state_span, "{state}");
+                        },
+                        Some(change) = sub.next() => if let Ok(state) = change.get().await {
+                            info!(parent: &state_span, "{state}");
+                        },
+                        _ = tick.tick() => for entry in journal.poll() {
+                            let key = (self.daemon, self.info.name.as_str());
+                            if journal_units(&entry).any(|unit| unit == key) {
+                                info!(parent: &journal_span, "{}", entry.message());
+                            }
+                        },
+                    }
+                }
+            };
+
+            tokio::select! {
+                result = wait => result,
+                never = watch => never,
+            }
+        };
+
+        async {
+            let result = job.await;
+            match &result {
+                Ok(result) if result.is_success() => info!("{result}"),
+                Ok(result) => warn!("{result}"),
+                Err(e) => warn!("{e}"),
+            }
+            result
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// the unit file to enable, units without a fragment or not loaded can still be enabled
+    /// by name
+    async fn unit_file(&self) -> Result<String, SysError> {
+        let path = match &self.proxy {
+            Some(proxy) => proxy.fragment_path().await?,
+            None => String::new(),
+        };
+        Ok(match path.is_empty() {
+            true => self.info.name.clone(),
+            false => path,
+        })
+    }
+
+    /// returns the unit file it went by
+    pub async fn enable(
+        &self,
+        runtime: bool,
+        force: bool,
+    ) -> Result<(String, Vec<UnitFileChange>), SysError> {
+        let file = self.unit_file().await?;
+        let (_, changes) = self
+            .manager
+            .enable_unit_files(vec![file.clone()], runtime, force)
+            .await?;
+        Ok((
+            file,
+            changes.into_iter().map(UnitFileChange::from).collect(),
+        ))
+    }
+
+    /// returns the unit file it went by
+    pub async fn reenable(
+        &self,
+        runtime: bool,
+        force: bool,
+    ) -> Result<(String, Vec<UnitFileChange>), SysError> {
+        let file = self.unit_file().await?;
+        let (_, changes) = self
+            .manager
+            .reenable_unit_files(vec![file.clone()], runtime, force)
+            .await?;
+        Ok((
+            file,
+            changes.into_iter().map(UnitFileChange::from).collect(),
+        ))
+    }
+
+    pub async fn disable(&self, runtime: bool) -> Result<Vec<UnitFileChange>, SysError> {
+        // DisableUnitFiles only takes names, a path is InvalidArgs
+        let changes = self
+            .manager
+            .disable_unit_files(vec![self.info.name.clone()], runtime)
+            .await?;
+        Ok(changes.into_iter().map(UnitFileChange::from).collect())
+    }
+
+    /// link the unit to /dev/null so nothing can start it, with `runtime` in /run so only
+    /// until reboot
+    pub async fn mask(&self, runtime: bool, force: bool) -> Result<Vec<UnitFileChange>, SysError> {
+        let changes = self
+            .manager
+            .mask_unit_files(vec![self.info.name.clone()], runtime, force)
+            .await?;
+        Ok(changes.into_iter().map(UnitFileChange::from).collect())
+    }
+
+    /// with `runtime` only a mask in /run is removed
+    pub async fn unmask(&self, runtime: bool) -> Result<Vec<UnitFileChange>, SysError> {
+        let changes = self
+            .manager
+            .unmask_unit_files(vec![self.info.name.clone()], runtime)
+            .await?;
+        Ok(changes.into_iter().map(UnitFileChange::from).collect())
+    }
+}
+
+/// a symlink systemd created or removed to enable, disable, mask or unmask a unit
+#[derive(Debug, Clone, serde::Serialize, PartialEq, Eq)]
+pub struct UnitFileChange {
+    /// "symlink" or "unlink"
+    #[serde(rename = "type")]
+    pub change: String,
+    pub path: String,
+    /// where a symlink points, empty when unlinked
+    pub target: String,
+}
+
+impl From<(String, String, String)> for UnitFileChange {
+    fn from((change, path, target): (String, String, String)) -> Self {
+        UnitFileChange {
+            change,
+            path,
+            target,
+        }
+    }
+}
+
+impl Display for UnitFileChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.target.is_empty() {
+            true => write!(f, "{} {}", self.change, self.path),
+            false => write!(f, "{} {} → {}", self.change, self.path, self.target),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! the library on its own, against the same fake systemd as cli.rs
+mod mock;
+
+use mock::{
+    MockSystemd,
+    MockUnit,
+};
+use sys::{
+    connect,
+    journal::JournalFollower,
+    properties::{
+        for_each_unit,
+        PropertyCache,
+        UNIT_INTERFACE,
+    },
+    state::{
+        ActiveState,
+        LoadState,
+        StateOpt,
+    },
+    Daemon,
+    DaemonType,
+    Filter,
+    JobResult,
+    State,
+    StateFilter,
+    TypeOpt,
+    UnitAction,
+};
+
+macro_rules! mock {
+    ($($unit:expr),* $(,)?) => {
+        MockSystemd::start([$($unit),*]).await
+    };
+}
+
+async fn daemon(mock: &MockSystemd) -> Daemon<'static> {
+    let conn = connect::address_connection(&mock.address).await.unwrap();
+    Daemon::new(DaemonType::System, &conn).await.unwrap()
+}
+
+#[tokio::test]
+async fn queries_units_with_a_filter() {
+    let mock = mock!(
+        MockUnit::new("web.service").state("active", "running"),
+        MockUnit::new("web.socket").state("active", "listening"),
+        MockUnit::new("backup.service"),
+        MockUnit::new("idle.service").unloaded(),
+    );
+    let daemon = daemon(&mock).await;
+
+    let filter = Filter {
+        types: vec![TypeOpt::Service],
+        exclude: StateFilter::new(&[StateOpt::Active(ActiveState::Active)]),
+        ..Default::default()
+    };
+    let (units, errors) = daemon.units(&filter).await.unwrap();
+    assert!(errors.is_empty(), "{errors:?}");
+    let names: Vec<_> = units.iter().map(|unit| unit.info.name.as_str()).collect();
+    assert_eq!(names, ["backup.service", "idle.service"]);
+    assert_eq!(units[0].info.active, State::Known(ActiveState::Inactive));
+}
+
+#[tokio::test]
+async fn runs_jobs_on_units() {
+    let mock = mock!(MockUnit::new("backup.service"));
+    let daemon = daemon(&mock).await;
+
+    let filter = Filter {
+        patterns: vec![Filter::fixed_string("backup")],
+        ..Default::default()
+    };
+    let (units, _) = daemon.units(&filter).await.unwrap();
+    let mut journal = JournalFollower::new(&[]);
+    let result = units[0]
+        .run_job(UnitAction::Start, &mut journal)
+        .await
+        .unwrap();
+    assert_eq!(result, JobResult::Done);
+    assert_eq!(mock.unit("backup.service").sub_state, "running");
+
+    assert_eq!(
+        units[0].enable(false, false).await.unwrap().0,
+        "/etc/systemd/system/backup.service"
+    );
+    assert_eq!(mock.unit("backup.service").unit_file_state, "enabled");
+}
+
+#[tokio::test]
+async fn caches_properties_until_forgotten() {
+    let mock = mock!(
+        MockUnit::new("backup.service"),
+        MockUnit::new("idle.service").unloaded(),
+    );
+    let daemon = daemon(&mock).await;
+
+    let (mut units, _) = daemon.units(&Filter::default()).await.unwrap();
+    units[1].load().await.unwrap();
+    assert_eq!(units[1].info.loaded, State::Known(LoadState::Loaded));
+
+    let cache = &PropertyCache::default();
+    let active_states = || {
+        for_each_unit(&units, move |unit| async move {
+            let properties = cache.get(unit, UNIT_INTERFACE).await.unwrap();
+            properties.get::<String>("ActiveState").unwrap()
+        })
+    };
+    assert_eq!(active_states().await, ["inactive", "inactive"]);
+
+    let mut journal = JournalFollower::new(&[]);
+    units[0]
+        .run_job(UnitAction::Start, &mut journal)
+        .await
+        .unwrap();
+    assert_eq!(active_states().await, ["inactive", "inactive"]);
+    cache.forget(&units[0]);
+    assert_eq!(active_states().await, ["active", "inactive"]);
+}
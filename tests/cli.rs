@@ -0,0 +1,906 @@
+//! sys against a fake systemd, see mock/mod.rs
+mod mock;
+
+use std::{
+    path::Path,
+    process::{
+        Output,
+        Stdio,
+    },
+    time::Duration,
+};
+
+use mock::{
+    MockSystemd,
+    MockUnit,
+};
+use tokio::{
+    io::{
+        AsyncBufReadExt,
+        BufReader,
+        Lines,
+    },
+    process::ChildStdout,
+};
+
+macro_rules! mock {
+    ($($unit:expr),* $(,)?) => {
+        MockSystemd::start([$($unit),*]).await
+    };
+}
+
+async fn sys(mock: &MockSystemd, args: &[&str]) -> Output {
+    let run = tokio::process::Command::new(env!("CARGO_BIN_EXE_sys"))
+        .arg("--bus-address")
+        .arg(&mock.address)
+        .args(args)
+        .env_remove("SYS_BUS_ADDRESS")
+        .output();
+    tokio::time::timeout(Duration::from_secs(10), run)
+        .await
+        .expect("sys did not finish")
+        .expect("could not run sys")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn units() -> [MockUnit; 4] {
+    [
+        MockUnit::new("web.service").state("active", "running"),
+        MockUnit::new("web.socket").state("active", "listening"),
+        MockUnit::new("backup.service"),
+        MockUnit::new("backup.timer").state("active", "waiting"),
+    ]
+}
+
+#[tokio::test]
+async fn lists_matching_units() {
+    let [web, socket, backup, _] = units();
+    let mock = mock!(web, socket, backup);
+
+    let output = sys(&mock, &["web"]).await;
+    let out = stdout(&output);
+    assert!(output.status.success(), "{output:?}");
+    assert!(out.contains("web.service"), "{out}");
+    assert!(out.contains("web.socket"), "{out}");
+    assert!(!out.contains("backup"), "{out}");
+}
+
+#[tokio::test]
+async fn filters_by_type() {
+    let [a, b, c, d] = units();
+    let mock = mock!(a, b, c, d);
+
+    // with a single type only the base names are listed
+    let out = stdout(&sys(&mock, &["-t", "timer", "."]).await);
+    assert_eq!(
+        out.split_whitespace().collect::<Vec<_>>(),
+        ["backup"],
+        "{out}"
+    );
+}
+
+#[tokio::test]
+async fn filters_by_state() {
+    let [a, b, c, d] = units();
+    let mock = mock!(a, b, c, d);
+
+    let out = stdout(&sys(&mock, &["-s", "running", "."]).await);
+    assert!(out.contains("web.service"), "{out}");
+    assert!(!out.contains("backup"), "{out}");
+
+    let out = stdout(&sys(&mock, &["-x", "active", "."]).await);
+    assert!(out.contains("backup.service"), "{out}");
+    assert!(!out.contains("web"), "{out}");
+}
+
+#[tokio::test]
+async fn fixed_strings_match_whole_names() {
+    let [a, b, c, d] = units();
+    let mock = mock!(a, b, c, d);
+
+    let out = stdout(&sys(&mock, &["-F", "web.socket"]).await);
+    assert!(out.contains("web.socket"), "{out}");
+    assert!(!out.contains("web.service"), "{out}");
+}
+
+#[tokio::test]
+async fn lists_unit_files_without_loading_them() {
+    let mock = mock!(
+        MockUnit::new("idle.service").unloaded(),
+        MockUnit::new("idle.timer").state("active", "waiting"),
+    );
+
+    let output = sys(&mock, &["-o", "json", "idle"]).await;
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("not json");
+    let unit = &json["units"][0];
+    assert_eq!(unit["name"], "idle.service");
+    assert_eq!(unit["load_state"], "not-loaded");
+    assert_eq!(unit["active_state"], "inactive");
+    assert_eq!(unit["object_path"], serde_json::Value::Null);
+    assert!(!mock.unit("idle.service").loaded);
+
+    let out = stdout(&sys(&mock, &["--no-unloaded", "idle"]).await);
+    assert_eq!(out.split_whitespace().collect::<Vec<_>>(), ["idle.timer"]);
+
+    // a property column needs the unit loaded
+    let out = stdout(&sys(&mock, &["--columns", "name,fragment-path", "idle"]).await);
+    assert!(out.contains("idle.service"), "{out}");
+    assert!(mock.unit("idle.service").loaded);
+}
+
+#[tokio::test]
+async fn jobs_load_unit_files_first() {
+    let mock = mock!(MockUnit::new("idle.service").unloaded());
+
+    let output = sys(&mock, &["-S", "-F", "idle.service"]).await;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(mock.calls(), ["Start idle.service replace"]);
+    assert_eq!(mock.unit("idle.service").sub_state, "running");
+}
+
+#[tokio::test]
+async fn no_match_exits_not_found() {
+    let [a, b, c, d] = units();
+    let mock = mock!(a, b, c, d);
+
+    let output = sys(&mock, &["nothing-like-this"]).await;
+    assert_eq!(output.status.code(), Some(3), "{output:?}");
+}
+
+#[tokio::test]
+async fn start_runs_a_job() {
+    let mock = mock!(MockUnit::new("backup.service"));
+
+    let output = sys(&mock, &["-S", "-F", "backup.service"]).await;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(mock.calls(), ["Start backup.service replace"]);
+    let unit = mock.unit("backup.service");
+    assert_eq!(
+        (unit.active_state.as_str(), unit.sub_state.as_str()),
+        ("active", "running")
+    );
+}
+
+#[tokio::test]
+async fn actions_on_several_units_need_force_without_a_terminal() {
+    let [a, b, c, d] = units();
+    let mock = mock!(a, b, c, d);
+
+    // there is nobody to ask
+    let output = sys(&mock, &["-K", "web"]).await;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("-f"),
+        "{output:?}"
+    );
+    assert!(mock.calls().is_empty());
+
+    let output = sys(&mock, &["-f", "-K", "web"]).await;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        mock.calls(),
+        ["Stop web.service replace", "Stop web.socket replace"]
+    );
+    assert_eq!(mock.unit("web.socket").sub_state, "dead");
+}
+
+#[tokio::test]
+async fn picking_units_needs_a_terminal() {
+    let [a, b, c, d] = units();
+    let mock = mock!(a, b, c, d);
+
+    let output = sys(&mock, &["-i", "-f", "-K", "web"]).await;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(mock.calls().is_empty());
+    assert_eq!(mock.unit("web.socket").sub_state, "listening");
+}
+
+#[tokio::test]
+async fn failed_job_exits_with_job_failed() {
+    let mock = mock!(MockUnit::new("broken.service").job_result("failed"));
+
+    let output = sys(&mock, &["-R", "-F", "broken.service"]).await;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert!(stdout(&output).contains("failed"), "{output:?}");
+    assert_eq!(mock.unit("broken.service").active_state, "failed");
+}
+
+#[tokio::test]
+async fn dependency_job_result_is_a_failure() {
+    let mock = mock!(MockUnit::new("needy.service").job_result("dependency"));
+
+    let output = sys(&mock, &["-S", "-F", "needy.service"]).await;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+}
+
+#[tokio::test]
+async fn newer_and_unknown_job_results_are_failures() {
+    for result in ["frozen", "concurrency", "not-yet-invented"] {
+        let mock = mock!(MockUnit::new("web.service").job_result(result));
+
+        let output = sys(&mock, &["-S", "-F", "web.service"]).await;
+        assert_eq!(output.status.code(), Some(1), "{result}: {output:?}");
+        assert!(stdout(&output).contains(result), "{output:?}");
+    }
+}
+
+#[tokio::test]
+async fn enable_and_disable_unit_files() {
+    let mock = mock!(MockUnit::new("web.service"));
+
+    let output = sys(&mock, &["-1", "-F", "web.service"]).await;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(mock.unit("web.service").unit_file_state, "enabled");
+
+    let output = sys(&mock, &["-0", "-F", "web.service"]).await;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        mock.calls(),
+        [
+            "EnableUnitFiles /etc/systemd/system/web.service",
+            "DisableUnitFiles web.service",
+        ]
+    );
+    assert_eq!(mock.unit("web.service").unit_file_state, "disabled");
+}
+
+#[tokio::test]
+async fn mask_and_unmask_print_what_changed() {
+    let mock = mock!(MockUnit::new("web.service"));
+
+    let output = sys(&mock, &["--mask", "--runtime", "-F", "web.service"]).await;
+    assert!(output.status.success(), "{output:?}");
+    let out = stdout(&output);
+    assert!(out.contains("mask web.service"), "{out}");
+    assert!(
+        out.contains("symlink /run/systemd/system/web.service → /dev/null"),
+        "{out}"
+    );
+    assert_eq!(mock.unit("web.service").unit_file_state, "masked-runtime");
+
+    // --runtime removes the mask in /run
+    let output = sys(&mock, &["--unmask", "--runtime", "-F", "web.service"]).await;
+    assert!(output.status.success(), "{output:?}");
+    assert!(stdout(&output).contains("unlink /run/systemd/system/web.service"));
+
+    let output = sys(
+        &mock,
+        &["--unmask", "-1", "-o", "json", "-F", "web.service"],
+    )
+    .await;
+    assert!(output.status.success(), "{output:?}");
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("not json");
+    let actions = &json["actions"];
+    assert_eq!(actions[0]["action"], "unmask");
+    assert_eq!(actions[1]["action"], "enable");
+    assert_eq!(actions[1]["changes"][0]["type"], "symlink");
+    assert_eq!(
+        mock.calls(),
+        [
+            "MaskUnitFiles web.service runtime",
+            "UnmaskUnitFiles web.service runtime",
+            "UnmaskUnitFiles web.service",
+            "EnableUnitFiles /etc/systemd/system/web.service",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn unknown_states_do_not_abort() {
+    let mock = mock!(MockUnit::new("future.service").state("active", "hyperdrive"));
+
+    let output = sys(&mock, &["-v", "--no-abbr", "future"]).await;
+    assert!(output.status.success(), "{output:?}");
+    assert!(stdout(&output).contains("hyperdrive"), "{output:?}");
+}
+
+#[tokio::test]
+async fn filters_on_states_it_does_not_know() {
+    let mock = mock!(
+        MockUnit::new("future.service").state("active", "hyperdrive"),
+        MockUnit::new("web.service").state("active", "running"),
+    );
+
+    let out = stdout(&sys(&mock, &["-s", "hyperdrive", "."]).await);
+    assert!(out.contains("future.service"), "{out}");
+    assert!(!out.contains("web.service"), "{out}");
+
+    let out = stdout(&sys(&mock, &["-x", "HyperDrive", "."]).await);
+    assert!(!out.contains("future.service"), "{out}");
+    assert!(out.contains("web.service"), "{out}");
+
+    // and the ones it knows in any case
+    let out = stdout(&sys(&mock, &["-s", "Running", "."]).await);
+    assert!(!out.contains("future.service"), "{out}");
+    assert!(out.contains("web.service"), "{out}");
+}
+
+#[tokio::test]
+async fn json_output_describes_units() {
+    let mock = mock!(MockUnit::new("web.service").state("active", "running"));
+
+    let output = sys(&mock, &["-o", "json", "web"]).await;
+    assert!(output.status.success(), "{output:?}");
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("not json");
+    let unit = &json["units"][0];
+    assert_eq!(unit["name"], "web.service");
+    assert_eq!(unit["base_name"], "web");
+    assert_eq!(unit["type"], "service");
+    assert_eq!(unit["load_state"], "loaded");
+    assert_eq!(unit["active_state"], "active");
+    assert_eq!(unit["sub_state"], "running");
+    assert_eq!(
+        unit["object_path"],
+        "/org/freedesktop/systemd1/unit/web_2eservice"
+    );
+    assert_eq!(unit["unit_file_state"], "disabled");
+    assert_eq!(json["actions"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn lists_automounts_and_types_it_does_not_know() {
+    let mock = mock!(
+        MockUnit::new("home.automount").state("active", "waiting"),
+        MockUnit::new("foo.device2").state("active", "plugged-in"),
+    );
+
+    let output = sys(&mock, &["-o", "json", "."]).await;
+    assert!(output.status.success(), "{output:?}");
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("not json");
+    let units = json["units"].as_array().unwrap();
+    let automount = units
+        .iter()
+        .find(|u| u["name"] == "home.automount")
+        .unwrap();
+    assert_eq!(automount["type"], "automount");
+    assert_eq!(automount["sub_state"], "waiting");
+    let other = units.iter().find(|u| u["name"] == "foo.device2").unwrap();
+    assert_eq!(other["type"], "device2");
+    assert_eq!(other["base_name"], "foo");
+    assert_eq!(other["sub_state"], "plugged-in");
+
+    let out = stdout(&sys(&mock, &["-t", "automount", "."]).await);
+    assert_eq!(
+        out.split_whitespace().collect::<Vec<_>>(),
+        ["home"],
+        "{out}"
+    );
+
+    // the unknown type gets the plain color in the table
+    let output = sys(&mock, &["."]).await;
+    let out = stdout(&output);
+    assert!(output.status.success(), "{output:?}");
+    assert!(out.contains("home.automount"), "{out}");
+    assert!(out.contains("foo.device2"), "{out}");
+}
+
+#[tokio::test]
+async fn ndjson_output_includes_job_results() {
+    let mock = mock!(MockUnit::new("broken.service").job_result("failed"));
+
+    let output = sys(&mock, &["-o", "ndjson", "-S", "-F", "broken.service"]).await;
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    let records: Vec<serde_json::Value> = stdout(&output)
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("not json"))
+        .collect();
+    assert_eq!(records.len(), 2, "{records:?}");
+    assert_eq!(records[0]["record"], "unit");
+    assert_eq!(records[1]["record"], "action");
+    assert_eq!(records[1]["action"], "start");
+    assert_eq!(records[1]["result"], "failed");
+}
+
+#[tokio::test]
+async fn csv_output_has_a_row_per_unit() {
+    let [a, b, c, d] = units();
+    let mock = mock!(a, b, c, d);
+
+    let output = sys(&mock, &["-o", "csv", "web"]).await;
+    assert!(output.status.success(), "{output:?}");
+    let out = stdout(&output);
+    let lines: Vec<_> = out.lines().collect();
+    assert!(
+        lines[0].starts_with("daemon,uid,name,base_name,type,"),
+        "{out}"
+    );
+    assert_eq!(lines.len(), 3, "{out}");
+    assert!(
+        lines[1].starts_with("system,,web.service,web,service,loaded,active,running,"),
+        "{out}"
+    );
+}
+
+#[tokio::test]
+async fn csv_output_takes_no_actions() {
+    let mock = mock!(MockUnit::new("web.service"));
+
+    let output = sys(&mock, &["-o", "csv", "-S", "web"]).await;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(stdout(&output).is_empty(), "{output:?}");
+    assert!(mock.calls().is_empty(), "{:?}", mock.calls());
+}
+
+#[tokio::test]
+async fn picks_columns_and_sorts() {
+    let mock = mock!(
+        MockUnit::new("small.service")
+            .state("active", "running")
+            .memory(1 << 20),
+        MockUnit::new("big.service")
+            .state("active", "running")
+            .memory(3 << 30),
+        MockUnit::new("idle.service"),
+    );
+
+    let output = sys(
+        &mock,
+        &[
+            "--columns",
+            "name,memory,main-pid",
+            "--sort",
+            "memory",
+            "service",
+        ],
+    )
+    .await;
+    assert!(output.status.success(), "{output:?}");
+    let out = stdout(&output);
+    let lines: Vec<Vec<_>> = out
+        .lines()
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            vec!["UNIT", "MEMORY", "PID"],
+            vec!["big.service", "3.0G", "4242"],
+            vec!["small.service", "1.0M", "4242"],
+            vec!["idle.service"],
+        ],
+        "{out}"
+    );
+}
+
+#[tokio::test]
+async fn where_expressions_combine_states_types_and_files() {
+    let mock = mock!(
+        MockUnit::new("web.service")
+            .state("failed", "failed")
+            .unit_file_state("enabled"),
+        MockUnit::new("worker.service").state("failed", "failed"),
+        MockUnit::new("web.socket").state("failed", "failed"),
+        MockUnit::new("backup.service"),
+        MockUnit::new("idle.service")
+            .unloaded()
+            .unit_file_state("enabled"),
+    );
+
+    let names = |output: Output| {
+        assert!(output.status.success(), "{output:?}");
+        stdout(&output)
+            .split_whitespace()
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+
+    // a unit file that can't match is not loaded just to find out
+    let out = names(sys(&mock, &["-w", "!enabled", "."]).await);
+    assert_eq!(out, ["backup.service", "web.socket", "worker.service"]);
+    assert!(!mock.unit("idle.service").loaded);
+
+    let out = names(
+        sys(
+            &mock,
+            &["-w", "active=failed & type=service & !enabled", "."],
+        )
+        .await,
+    );
+    assert_eq!(out, ["worker.service"]);
+
+    let out = names(sys(&mock, &["-w", "failed & !(socket | name=^web)", "."]).await);
+    assert_eq!(out, ["worker.service"]);
+
+    let out = names(sys(&mock, &["-w", "dead | file=enabled & type=socket", "."]).await);
+    assert_eq!(out, ["backup.service", "idle.service"]);
+}
+
+#[tokio::test]
+async fn filters_by_unit_file_state() {
+    let mock = mock!(
+        MockUnit::new("web.service")
+            .state("active", "running")
+            .unit_file_state("enabled"),
+        MockUnit::new("backup.service").unit_file_state("enabled"),
+        MockUnit::new("tmp.mount").unit_file_state("static"),
+        MockUnit::new("old.service").unit_file_state("masked"),
+        MockUnit::new("idle.service"),
+    );
+
+    let names = |output: Output| {
+        assert!(output.status.success(), "{output:?}");
+        stdout(&output)
+            .split_whitespace()
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+
+    // enabled but not running
+    let out = names(sys(&mock, &["--enabled", "-x", "running", "."]).await);
+    assert_eq!(out, ["backup.service"]);
+
+    let out = names(sys(&mock, &["--static", "--masked", "."]).await);
+    assert_eq!(out, ["old.service", "tmp.mount"]);
+
+    let out = stdout(&sys(&mock, &["-v", "--disabled", "."]).await);
+    assert!(out.contains("idle.service"), "{out}");
+    assert!(out.contains("disabled"), "{out}");
+    assert!(!out.contains("web"), "{out}");
+}
+
+#[tokio::test]
+async fn bad_where_expressions_are_usage_errors() {
+    let mock = mock!(MockUnit::new("web.service"));
+
+    for expr in ["active=", "(failed", "failed &", "colour=red", "sometimes"] {
+        let output = sys(&mock, &["-w", expr, "."]).await;
+        assert_eq!(output.status.code(), Some(2), "{expr}: {output:?}");
+    }
+}
+
+#[tokio::test]
+async fn completes_live_unit_names_and_states() {
+    let [web, socket, backup, timer] = units();
+    let future = MockUnit::new("future.service").state("active", "hyperdrive");
+    let mock = mock!(web, socket, backup, timer, future);
+
+    // the scripts pass the line as the shell split it, with the word to complete at INDEX
+    let complete = |words: &[&str]| {
+        let index = (words.len() - 1).to_string();
+        let run = tokio::process::Command::new(env!("CARGO_BIN_EXE_sys"))
+            .arg("__complete")
+            .arg(index)
+            .args(words)
+            .env("SYS_BUS_ADDRESS", &mock.address)
+            .output();
+        async move {
+            let output = tokio::time::timeout(Duration::from_secs(10), run)
+                .await
+                .expect("sys did not finish")
+                .expect("could not run sys");
+            assert!(output.status.success(), "{output:?}");
+            stdout(&output)
+                .lines()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        }
+    };
+
+    assert_eq!(
+        complete(&["sys", "we"]).await,
+        ["web.service", "web.socket"]
+    );
+    assert_eq!(
+        complete(&["sys", "-F", "b"]).await,
+        ["backup.service", "backup.timer"]
+    );
+    assert_eq!(
+        complete(&["sys", "-t", ""]).await,
+        ["service", "socket", "timer"]
+    );
+    assert_eq!(complete(&["sys", "-s", "run"]).await, ["running"]);
+    assert_eq!(complete(&["sys", "-x", "hyp"]).await, ["hyperdrive"]);
+    // options and the values of other options are left to the static script
+    assert!(complete(&["sys", "--sta"]).await.is_empty());
+    assert!(complete(&["sys", "-n", ""]).await.is_empty());
+
+    let script = stdout(&sys(&mock, &["--completions", "bash"]).await);
+    assert!(script.contains("sys __complete"), "{script}");
+    assert!(script.contains("complete -F _sys_live"), "{script}");
+}
+
+#[tokio::test]
+async fn tui_needs_a_terminal() {
+    let mock = mock!(MockUnit::new("web.service"));
+
+    let run = tokio::process::Command::new(env!("CARGO_BIN_EXE_sys"))
+        .args(["tui", "web"])
+        .env("SYS_BUS_ADDRESS", &mock.address)
+        .output();
+    let output = tokio::time::timeout(Duration::from_secs(10), run)
+        .await
+        .expect("sys did not finish")
+        .expect("could not run sys");
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+}
+
+#[tokio::test]
+async fn templates_list_instances_and_make_new_ones() {
+    let mock = mock!(
+        MockUnit::new("getty@.service").unloaded(),
+        MockUnit::new("getty@tty1.service").state("active", "running"),
+        MockUnit::new("serial-getty@.service").unloaded(),
+        MockUnit::new("web.service"),
+    );
+
+    // instances right under their template
+    let output = sys(&mock, &["--template", "getty"]).await;
+    let out = stdout(&output);
+    assert!(output.status.success(), "{output:?}");
+    let lines = out.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2, "{out}");
+    assert!(lines[0].contains("getty@.service"), "{out}");
+    assert!(lines[1].contains("└ @tty1"), "{out}");
+    assert!(!out.contains("web"), "{out}");
+
+    let output = sys(&mock, &["-o", "json", "--instance", "tty1", "."]).await;
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).expect("not json");
+    let units = json["units"].as_array().unwrap();
+    assert_eq!(units.len(), 1, "{json}");
+    assert_eq!(units[0]["name"], "getty@tty1.service");
+    assert_eq!(units[0]["template"], "getty@.service");
+    assert_eq!(units[0]["instance"], "tty1");
+
+    // no template was named, so no instances are made up
+    let output = sys(&mock, &["-o", "json", "--instance", "tty2", "."]).await;
+    assert_eq!(output.status.code(), Some(3), "{output:?}");
+
+    // a new instance from the template
+    let output = sys(&mock, &["-f", "-S", "getty@", "--instance", "tty2"]).await;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(mock.calls(), ["Start getty@tty2.service replace"]);
+    assert_eq!(mock.unit("getty@tty2.service").active_state, "active");
+}
+
+#[tokio::test]
+async fn cat_rebuilds_the_unit_file_from_properties() {
+    let dir = std::env::temp_dir().join(format!("sys-cat-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let fragment = dir.join("web.service");
+    std::fs::write(
+        &fragment,
+        "[Unit]\nDescription=web\n\n[Service]\nExecStart=/usr/bin/web.service --serve\n\n\
+         [Install]\n# not this one\nWantedBy=multi-user.target\n",
+    )
+    .unwrap();
+    let mock = mock!(MockUnit {
+        fragment_path: fragment.display().to_string(),
+        ..MockUnit::new("web.service")
+    });
+
+    let cat = |args: &'static [&'static str]| {
+        let run = tokio::process::Command::new(env!("CARGO_BIN_EXE_sys"))
+            .arg("cat")
+            .args(args)
+            .env("SYS_BUS_ADDRESS", &mock.address)
+            .output();
+        async {
+            tokio::time::timeout(Duration::from_secs(10), run)
+                .await
+                .expect("sys did not finish")
+                .expect("could not run sys")
+        }
+    };
+
+    let output = cat(&["--effective", "web.service"]).await;
+    let out = stdout(&output);
+    assert!(output.status.success(), "{output:?}");
+    for line in [
+        "[Unit]",
+        "Description=mock web.service",
+        "Documentation=man:web.service(8)",
+        "After=network-online.target basic.target",
+        "ConditionPathExists=!/etc/mock/disabled",
+        "[Service]",
+        "ExecStart=/usr/bin/web.service --serve",
+        "Environment=MODE=mock \"GREETING=hello world\"",
+        "LimitNOFILE=1024:524288",
+        "RemainAfterExit=no",
+        "TimeoutStartSec=1min 30s",
+        "Type=simple",
+        "[Install]",
+        "WantedBy=multi-user.target",
+    ] {
+        assert!(out.lines().any(|l| l == line), "no {line} in\n{out}");
+    }
+    // state is not a setting
+    assert!(!out.contains("MainPID") && !out.contains("Memory"), "{out}");
+    assert!(!out.contains("LimitNOFILESoft"), "{out}");
+
+    let output = cat(&["web.service"]).await;
+    let out = stdout(&output);
+    assert!(output.status.success(), "{output:?}");
+    assert!(out.contains(&format!("# {}", fragment.display())), "{out}");
+    assert!(out.contains("# not this one"), "{out}");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn status_shows_what_the_unit_has() {
+    let mut web = MockUnit::new("web.service")
+        .state("active", "running")
+        .unit_file_state("enabled")
+        .memory(12 << 20);
+    web.drop_in_paths = vec!["/etc/systemd/system/web.service.d/override.conf".to_string()];
+    let mock = mock!(web, MockUnit::new("backup.service").unloaded());
+
+    let output = sys(&mock, &["-Q", "-m", "web", "backup"]).await;
+    let out = console::strip_ansi_codes(&stdout(&output)).into_owned();
+    assert!(output.status.success(), "{output:?}");
+    for line in [
+        "● web.service - mock web.service",
+        "Loaded: loaded (/etc/systemd/system/web.service; enabled",
+        "Drop-In: /etc/systemd/system/web.service.d/override.conf",
+        "Active: active (running)",
+        "Main PID: 4242",
+        "Memory: 12.0M",
+        "CGroup: /system.slice/web.service",
+    ] {
+        assert!(out.contains(line), "no {line:?} in\n{out}");
+    }
+    // a unit file -Q had to LoadUnit first, like systemctl status does
+    assert!(
+        out.contains("backup.service - mock backup.service"),
+        "{out}"
+    );
+    assert!(out.contains("Active: inactive (dead)"), "{out}");
+
+    // what a timer triggers is said once
+    let mock = mock!(MockUnit::new("backup.timer").state("active", "waiting"));
+    let output = sys(&mock, &["-Q", "backup.timer"]).await;
+    let out = console::strip_ansi_codes(&stdout(&output)).into_owned();
+    assert_eq!(out.matches("Triggers: backup.service").count(), 1, "{out}");
+}
+
+/// sys with the users' buses at `dir/{uid}/bus` and logind on `login`
+async fn sys_users(dir: &Path, login: &MockSystemd, args: &[&str]) -> Output {
+    let run = tokio::process::Command::new(env!("CARGO_BIN_EXE_sys"))
+        .arg("--bus-address")
+        .arg(format!("unix:path={}/{{uid}}/bus", dir.display()))
+        .args(args)
+        .env_remove("SYS_BUS_ADDRESS")
+        .env("DBUS_SYSTEM_BUS_ADDRESS", &login.address)
+        .output();
+    tokio::time::timeout(Duration::from_secs(10), run)
+        .await
+        .expect("sys did not finish")
+        .expect("could not run sys")
+}
+
+#[tokio::test]
+async fn lists_the_units_of_several_users() {
+    let root = mock!(MockUnit::new("first.service"));
+    let other = mock!(MockUnit::new("second.service"));
+    let dir = std::env::temp_dir().join(format!("sys-users-{}", std::process::id()));
+    for (uid, mock) in [(0, &root), (1000, &other)] {
+        std::fs::create_dir_all(dir.join(uid.to_string())).unwrap();
+        std::os::unix::fs::symlink(mock.bus_path(), dir.join(format!("{uid}/bus"))).unwrap();
+    }
+    // logind lives on the first bus, the closing user has no bus to reach
+    root.log_in(0, "root", "active").await;
+    root.log_in(1000, "other", "active").await;
+    root.log_in(1001, "gone", "closing").await;
+
+    let output = sys_users(&dir, &root, &["--uid", "0", "--uid", "1000", "."]).await;
+    let out = stdout(&output);
+    assert!(output.status.success(), "{output:?}");
+    assert!(out.contains("first.service"), "{out}");
+    assert!(out.contains("second.service"), "{out}");
+
+    let output = sys_users(&dir, &root, &["--user=root", "."]).await;
+    let out = stdout(&output);
+    assert!(output.status.success(), "{output:?}");
+    assert!(out.contains("first.service"), "{out}");
+    assert!(!out.contains("second.service"), "{out}");
+
+    let output = sys_users(&dir, &root, &["--all-users", "."]).await;
+    let out = stdout(&output);
+    assert!(output.status.success(), "{output:?}");
+    assert!(out.contains("first.service"), "{out}");
+    assert!(out.contains("second.service"), "{out}");
+    assert!(output.stderr.is_empty(), "{output:?}");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// the lines up to where every one of `needles` was in one of them
+async fn read_until(lines: &mut Lines<BufReader<ChildStdout>>, needles: &[&str]) -> Vec<String> {
+    let mut read: Vec<String> = Vec::new();
+    while !needles
+        .iter()
+        .all(|needle| read.iter().any(|line| line.contains(needle)))
+    {
+        let line = tokio::time::timeout(Duration::from_secs(10), lines.next_line())
+            .await
+            .unwrap_or_else(|_| panic!("no {needles:?} in {read:#?}"))
+            .unwrap()
+            .unwrap_or_else(|| panic!("no {needles:?} before the end of {read:#?}"));
+        read.push(console::strip_ansi_codes(&line).into_owned());
+    }
+    read
+}
+
+#[tokio::test]
+async fn follow_prints_jobs_and_state_changes() {
+    let mock = mock!(
+        MockUnit::new("web.service"),
+        MockUnit::new("db.service"),
+        MockUnit::new("idle.service").unloaded(),
+    );
+    let journal = std::env::temp_dir().join(format!("sys-follow-{}", std::process::id()));
+    std::fs::create_dir_all(&journal).unwrap();
+
+    let mut follow = tokio::process::Command::new(env!("CARGO_BIN_EXE_sys"))
+        .arg("--bus-address")
+        .arg(&mock.address)
+        .arg("--journal-dir")
+        .arg(&journal)
+        .args(["--follow", "-m", "web.service", "idle.service"])
+        .env_remove("SYS_BUS_ADDRESS")
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .expect("could not run sys");
+    let mut lines = BufReader::new(follow.stdout.take().unwrap()).lines();
+    read_until(&mut lines, &["following 2 units"]).await;
+
+    // started from elsewhere, and a unit that is not followed
+    assert!(sys(&mock, &["-S", "db.service"]).await.status.success());
+    assert!(sys(&mock, &["-S", "web.service"]).await.status.success());
+
+    // signals of the job and of the unit can come in either order
+    let read = read_until(
+        &mut lines,
+        &["web.service: job 2 done", "→ active (running)"],
+    )
+    .await;
+    let has = |needle: &str| read.iter().any(|line| line.contains(needle));
+    assert!(has("web.service: job 2 start queued"), "{read:#?}");
+    // the mock changes active and sub state in two signals, systemd in one
+    assert!(
+        has("web.service: inactive (dead) → activating"),
+        "{read:#?}"
+    );
+    assert!(!has("db.service"), "{read:#?}");
+
+    // only a unit file when following started, its states are followed once it loads
+    assert!(sys(&mock, &["-Q", "idle.service"]).await.status.success());
+    read_until(&mut lines, &["idle.service: loaded"]).await;
+    assert!(sys(&mock, &["-S", "idle.service"]).await.status.success());
+    let read = read_until(
+        &mut lines,
+        &["idle.service: job 3 done", "→ active (running)"],
+    )
+    .await;
+    assert!(
+        read.iter()
+            .any(|line| line.contains("idle.service: inactive (dead) → activating")),
+        "{read:#?}"
+    );
+
+    follow.kill().await.unwrap();
+    std::fs::remove_dir_all(&journal).unwrap();
+}
+
+#[tokio::test]
+async fn journal_of_matching_units() {
+    let mock = mock!(MockUnit::new("web.service"), MockUnit::new("db.service"));
+    let rotated = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/journal/rotated");
+
+    // across the archive and the file journald went on with after rotating
+    let output = sys(&mock, &["-L", "--journal-dir", rotated, "web.service"]).await;
+    let out = stdout(&output);
+    assert!(output.status.success(), "{output:?}");
+    let messages = ["starting web", "Failed with result", "stopped web"];
+    let lines = out
+        .lines()
+        .filter(|line| messages.iter().any(|m| line.contains(m)))
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3, "{out}");
+    assert!(lines[0].contains("starting web") && lines[2].contains("stopped web"));
+    assert!(!out.contains("db noise"), "{out}");
+}
@@ -0,0 +1,142 @@
+//! the journal reader against files journald wrote, see journal/make.py for how
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use sys::journal::{
+    journal_files,
+    Entry,
+    JournalFile,
+};
+
+/// what web.service logged as a system unit, and what systemd logged about it
+const WEB: [&str; 3] = [
+    "starting web",
+    "web.service: Failed with result 'exit-code'.",
+    "stopped web",
+];
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/journal")
+        .join(name)
+}
+
+fn matches(pairs: &[&str]) -> Vec<String> {
+    pairs.iter().map(|pair| pair.to_string()).collect()
+}
+
+fn system_web() -> Vec<String> {
+    matches(&["UNIT=web.service", "_SYSTEMD_UNIT=web.service"])
+}
+
+fn messages<'e>(entries: impl IntoIterator<Item = &'e Entry>) -> Vec<&'e str> {
+    entries.into_iter().map(Entry::message).collect()
+}
+
+#[test]
+fn reads_every_format() {
+    for name in [
+        "regular", "compact", "zstd", "lz4", "xz",
+        // jenkins hashed, from before keyed hashes
+        "unkeyed",
+    ] {
+        let file = JournalFile::open(&fixture(&format!("{name}.journal"))).unwrap();
+
+        let entries = file
+            .entries_matching(&system_web())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_else(|e| panic!("{name}: {e}"));
+        assert_eq!(messages(&entries), WEB, "{name}");
+        assert_eq!(entries[0].field("_SYSTEMD_UNIT"), Some("web.service"));
+        assert_eq!(entries[1].priority(), 3, "{name}");
+        assert!(entries.windows(2).all(|w| w[0].realtime < w[1].realtime));
+
+        // the big one, compressed in all but regular, compact and unkeyed
+        let entries = file
+            .entries_matching(&matches(&["_SYSTEMD_USER_UNIT=web.service"]))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_else(|e| panic!("{name}: {e}"));
+        assert_eq!(entries.len(), 1, "{name}");
+        let message = entries[0].message();
+        assert_eq!(
+            message,
+            format!("listening on :8080 {}", "x".repeat(601)),
+            "{name}"
+        );
+        assert_eq!(entries[0].field("_SYSTEMD_OWNER_UID"), Some("1000"));
+        assert_eq!(entries[0].priority(), 5);
+    }
+}
+
+#[test]
+fn only_reads_what_matches() {
+    let file = JournalFile::open(&fixture("compact.journal")).unwrap();
+
+    let entries = file
+        .entries_matching(&matches(&["_SYSTEMD_UNIT=db.service"]))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(messages(&entries), ["db noise"]);
+    assert_eq!(entries[0].priority(), 4);
+
+    // a value that is nowhere, and one that is only a prefix of what is there
+    let entries = file
+        .entries_matching(&matches(&["UNIT=nope.service", "_SYSTEMD_UNIT=web"]))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert!(entries.is_empty());
+}
+
+#[test]
+fn reads_rotated_files() {
+    let files = journal_files(&[fixture("rotated")]);
+    assert_eq!(files.len(), 2, "{files:?}");
+    // the archive journald renamed the file to at rotation, and the one it went on with
+    assert!(files.iter().any(|f| f.ends_with("system.journal")));
+
+    let mut entries = Vec::new();
+    for path in &files {
+        let file = JournalFile::open(path).unwrap();
+        let found = file
+            .entries_matching(&system_web())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        // halfway through
+        assert!(!found.is_empty() && found.len() < WEB.len(), "{path:?}");
+        entries.extend(found);
+    }
+    entries.sort_by_key(|entry| entry.realtime);
+    assert_eq!(messages(&entries), WEB);
+    // the new file goes on counting where the archive stopped
+    assert!(entries.iter().all(|e| e.seqnum_id == entries[0].seqnum_id));
+    assert!(entries.windows(2).all(|w| w[0].seqnum < w[1].seqnum));
+}
+
+#[test]
+fn keeps_what_a_truncated_file_has() {
+    // cut in the middle of the last entry of web.service
+    let file = JournalFile::open(&fixture("truncated.journal")).unwrap();
+
+    let results = file.entries_matching(&system_web()).collect::<Vec<_>>();
+    let read = results
+        .iter()
+        .filter_map(|result| result.as_ref().ok())
+        .collect::<Vec<_>>();
+    assert_eq!(messages(read), WEB[..2]);
+    assert!(results.last().unwrap().is_err(), "{results:?}");
+
+    // tailing stops at the cut as well
+    let tailed = file
+        .entries_from(0)
+        .filter_map(Result::ok)
+        .collect::<Vec<_>>();
+    assert_eq!(messages(&tailed).last(), Some(&WEB[1]));
+}
+
+#[test]
+fn not_a_journal() {
+    let error = JournalFile::open(&fixture("make.py")).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+}
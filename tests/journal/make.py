@@ -0,0 +1,285 @@
+#!/usr/bin/env python3
+"""Writes the journal files tests/journal.rs reads, with a real systemd-journald.
+
+Needs root, systemd-journald and cgroupfs, run from anywhere: python3 tests/journal/make.py
+
+journald runs in its own namespace so the journal of the host is left alone, the logging
+processes are moved into unit cgroups for journald to fill in _SYSTEMD_UNIT and friends.
+journald only compresses with zstd, so the lz4 and xz files are written uncompressed and
+then have the big data object recompressed in place, to the exact same size so no other
+object moves: the lz4 block gets its sequences picked to fit, the xz stream pads its block
+header. `journalctl --verify` checks every file afterwards.
+"""
+import glob
+import lzma
+import os
+import shutil
+import signal
+import socket
+import struct
+import subprocess
+import sys
+import time
+import zlib
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+NAMESPACE = "sysfixture"
+CONF = f"/etc/systemd/journald@{NAMESPACE}.conf"
+SOCKET = f"/run/systemd/journal.{NAMESPACE}/socket"
+CGROUPS = ["/sys/fs/cgroup/unified", "/sys/fs/cgroup/systemd"]
+
+SYSTEM = "/system.slice/{}"
+USER = "/user.slice/user-1000.slice/user@1000.service/app.slice/{}"
+
+# compressed when asked for, and recompressed for lz4 and xz, a multiple of 4 long for xz
+BIG = "listening on :8080 " + "x" * 601
+assert len("MESSAGE=" + BIG) % 4 == 0
+
+# (cgroup of the sender, fields)
+MESSAGES = [
+    (SYSTEM.format("web.service"), {"MESSAGE": "starting web", "PRIORITY": "6"}),
+    (USER.format("web.service"), {"MESSAGE": BIG, "PRIORITY": "5"}),
+    (SYSTEM.format("db.service"), {"MESSAGE": "db noise", "PRIORITY": "4"}),
+    # what systemd itself logs about a unit
+    (None, {"MESSAGE": "web.service: Failed with result 'exit-code'.", "UNIT": "web.service",
+            "PRIORITY": "3"}),
+    (SYSTEM.format("web.service"), {"MESSAGE": "stopped web", "PRIORITY": "6"}),
+]
+
+
+def send(cgroup, fields):
+    """log the fields from a child process in cgroup, so journald sees it there"""
+    pid = os.fork()
+    if pid == 0:
+        if cgroup:
+            for root in CGROUPS:
+                os.makedirs(root + cgroup, exist_ok=True)
+                with open(root + cgroup + "/cgroup.procs", "w") as procs:
+                    procs.write(str(os.getpid()))
+        data = "".join(f"{name}={value}\n" for name, value in fields.items())
+        sock = socket.socket(socket.AF_UNIX, socket.SOCK_DGRAM)
+        sock.sendto(data.encode(), SOCKET)
+        # journald reads the cgroup of the sender from /proc, stay around for it
+        time.sleep(0.2)
+        os._exit(0)
+    os.waitpid(pid, 0)
+
+
+def journal_dir():
+    dirs = glob.glob(f"/run/log/journal/*.{NAMESPACE}")
+    return dirs[0] if dirs else None
+
+
+def run_journald(env, compress, rotate_after=None):
+    """a journald with env and Compress=, logging MESSAGES, the files it wrote"""
+    if journal_dir():
+        shutil.rmtree(journal_dir())
+    with open(CONF, "w") as conf:
+        conf.write(f"[Journal]\nStorage=volatile\nCompress={compress}\nSeal=no\n"
+                   "RuntimeMaxFileSize=512K\n")
+    journald = subprocess.Popen(["/usr/lib/systemd/systemd-journald", NAMESPACE],
+                                env={**os.environ, **env})
+    while not os.path.exists(SOCKET):
+        time.sleep(0.05)
+    time.sleep(0.3)
+    for i, (cgroup, fields) in enumerate(MESSAGES):
+        if i == rotate_after:
+            subprocess.run(["journalctl", f"--namespace={NAMESPACE}", "--rotate"], check=True)
+        send(cgroup, fields)
+    time.sleep(0.3)
+    journald.send_signal(signal.SIGTERM)
+    journald.wait()
+    files = sorted(glob.glob(journal_dir() + "/*.journal"))
+    return files
+
+
+def objects(data):
+    """(offset, type, flags, size) of every object in a journal file"""
+    offset = struct.unpack_from("<Q", data, 88)[0]
+    tail = struct.unpack_from("<Q", data, 136)[0]
+    while offset <= tail:
+        typ, flags, size = data[offset], data[offset + 1], struct.unpack_from("<Q", data, offset + 8)[0]
+        yield offset, typ, flags, size
+        offset += (size + 7) & ~7
+
+
+def ext_len(n):
+    """bytes lz4 needs past the token nibble for a length"""
+    return 0 if n < 15 else (n - 15) // 255 + 1
+
+
+def lz4_len(value):
+    out = b""
+    if value >= 15:
+        value -= 15
+        while value >= 255:
+            out += b"\xff"
+            value -= 255
+        out += bytes([value])
+    return out
+
+
+def lz4_block(content, size):
+    """an lz4 block of exactly size bytes: literals, a run of the last one, literals"""
+    prefix = content.rindex(b"MESSAGE=") + len(b"MESSAGE=listening on :8080 x")
+    for literal in range(prefix, len(content)):
+        for last in range(12, len(content) - literal - 4):
+            match = len(content) - literal - last
+            if content[literal:literal + match] != content[literal - 1:literal] * match:
+                continue
+            total = (1 + ext_len(literal) + literal + 2 + ext_len(match - 4)
+                     + 1 + ext_len(last) + last)
+            if total != size:
+                continue
+            token = (min(literal, 15) << 4) | min(match - 4, 15)
+            block = bytes([token]) + lz4_len(literal) + content[:literal]
+            block += struct.pack("<H", 1) + lz4_len(match - 4)
+            block += bytes([min(last, 15) << 4]) + lz4_len(last) + content[-last:]
+            assert len(block) == size
+            return block
+    raise SystemExit(f"no lz4 block of {size} bytes for {len(content)} bytes")
+
+
+def varint(n):
+    out = b""
+    while n >= 0x80:
+        out += bytes([n & 0x7F | 0x80])
+        n >>= 7
+    return out + bytes([n])
+
+
+def xz_stream(content, size):
+    """an xz stream without check of exactly size bytes, with a padded block header"""
+    raw = lzma.compress(content, format=lzma.FORMAT_RAW,
+                        filters=[{"id": lzma.FILTER_LZMA2, "preset": 6}])
+    stream_flags = b"\x00\x00"  # check none
+    header = b"\xfd7zXZ\x00" + stream_flags + struct.pack("<I", zlib.crc32(stream_flags))
+    props = lzma._encode_filter_properties({"id": lzma.FILTER_LZMA2, "preset": 6})
+    filters = varint(0x21) + varint(len(props)) + props
+
+    for padding in range(0, 1024, 4):
+        # block header: size, flags (one filter), the filter, padding, crc32
+        body = b"\x00" + filters
+        block_size = 1 + len(body) + 4
+        block_size = (block_size + 3) & ~3
+        block_size += padding
+        if block_size > 1024:
+            break
+        block_header = bytes([block_size // 4 - 1]) + body
+        block_header += b"\x00" * (block_size - 4 - len(block_header))
+        block_header += struct.pack("<I", zlib.crc32(block_header))
+
+        unpadded = len(block_header) + len(raw)
+        block = block_header + raw + b"\x00" * (-len(raw) % 4)
+
+        index = b"\x00" + varint(1) + varint(unpadded) + varint(len(content))
+        index += b"\x00" * (-len(index) % 4)
+        index += struct.pack("<I", zlib.crc32(index))
+
+        backward = struct.pack("<I", len(index) // 4 - 1) + stream_flags
+        footer = struct.pack("<I", zlib.crc32(backward)) + backward + b"YZ"
+
+        stream = header + block + index + footer
+        if len(stream) == size:
+            assert lzma.decompress(stream) == content
+            return stream
+    raise SystemExit(f"no xz stream of {size} bytes for {len(content)} bytes")
+
+
+def recompress(path, algorithm):
+    """recompress the big data object of a file in place, lz4 or xz"""
+    with open(path, "rb") as f:
+        data = bytearray(f.read())
+    incompatible = struct.unpack_from("<I", data, 12)[0]
+    compact = incompatible & (1 << 4) != 0
+    payload_offset = 72 if compact else 64
+    for offset, typ, flags, size in objects(data):
+        start, end = offset + payload_offset, offset + size
+        if typ != 1 or not data[start:end].startswith(b"MESSAGE=listening"):
+            continue
+        assert flags == 0, "already compressed"
+        content = bytes(data[start:end])
+        if algorithm == "lz4":
+            encoded = struct.pack("<Q", len(content)) + lz4_block(content, end - start - 8)
+            data[offset + 1] = 1 << 1
+            incompatible |= 1 << 1
+        else:
+            encoded = xz_stream(content, end - start)
+            data[offset + 1] = 1 << 0
+            incompatible |= 1 << 0
+        data[start:end] = encoded
+        struct.pack_into("<I", data, 12, incompatible)
+        with open(path, "wb") as f:
+            f.write(data)
+        return
+    raise SystemExit(f"no big data object in {path}")
+
+
+def shrink(path):
+    """cut the zeros after the tail object journald allocated ahead, and say so in the header"""
+    with open(path, "rb") as f:
+        data = bytearray(f.read())
+    header_size = struct.unpack_from("<Q", data, 88)[0]
+    tail = struct.unpack_from("<Q", data, 136)[0]
+    end = tail + ((struct.unpack_from("<Q", data, tail + 8)[0] + 7) & ~7)
+    struct.pack_into("<Q", data, 96, end - header_size)
+    with open(path, "wb") as f:
+        f.write(data[:end])
+
+
+def verify(path):
+    subprocess.run(["journalctl", "--file", path, "--verify", "-q"], check=True)
+    os.chmod(path, 0o644)
+
+
+def main():
+    if os.geteuid() != 0:
+        raise SystemExit("needs root, to run journald and move processes between cgroups")
+    plain = {"SYSTEMD_JOURNAL_COMPACT": "0"}
+    compact = {"SYSTEMD_JOURNAL_COMPACT": "1"}
+    unkeyed = {"SYSTEMD_JOURNAL_COMPACT": "0", "SYSTEMD_JOURNAL_KEYED_HASH": "0"}
+    variants = [
+        ("regular", plain, "no", None),
+        ("compact", compact, "no", None),
+        ("zstd", compact, "64", None),
+        ("lz4", plain, "no", "lz4"),
+        ("xz", compact, "no", "xz"),
+        ("unkeyed", unkeyed, "no", None),
+    ]
+    try:
+        for name, env, compress, algorithm in variants:
+            [written] = run_journald(env, compress)
+            path = f"{HERE}/{name}.journal"
+            shutil.copy(written, path)
+            shrink(path)
+            if algorithm:
+                recompress(path, algorithm)
+            verify(path)
+
+        # rotated halfway, the archive and the file journald went on with
+        rotated = f"{HERE}/rotated"
+        shutil.rmtree(rotated, ignore_errors=True)
+        os.makedirs(rotated)
+        for written in run_journald(compact, "no", rotate_after=3):
+            path = f"{rotated}/{os.path.basename(written)}"
+            shutil.copy(written, path)
+            shrink(path)
+            verify(path)
+
+        # cut short in the middle of the last entry of web.service, like a file journald
+        # was still writing to
+        with open(f"{HERE}/compact.journal", "rb") as f:
+            data = f.read()
+        stopped = data.index(b"MESSAGE=stopped web")
+        entry = min(offset for offset, typ, _, _ in objects(data) if typ == 3 and offset > stopped)
+        with open(f"{HERE}/truncated.journal", "wb") as f:
+            f.write(data[:entry + 24])
+        os.chmod(f"{HERE}/truncated.journal", 0o644)
+    finally:
+        os.remove(CONF)
+        if journal_dir():
+            shutil.rmtree(journal_dir())
+
+
+if __name__ == "__main__":
+    sys.exit(main())
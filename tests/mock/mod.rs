@@ -0,0 +1,985 @@
+//! An in-process fake of org.freedesktop.systemd1 on a private dbus-daemon.
+//!
+//! Only what sys uses is there: the Manager with its unit listing, unit file and job
+//! signals, Unit objects that run jobs, Service objects with a bit of accounting, and
+//! Job objects that live while their job runs. The bus has a logind too, with the users
+//! [`MockSystemd::log_in`] gave it. Jobs take a moment and end with whatever
+//! result the unit was given, so sys sees the same signal sequence as with systemd.
+#![allow(dead_code)]
+
+use std::{
+    collections::BTreeMap,
+    io::{
+        BufRead,
+        BufReader,
+    },
+    path::PathBuf,
+    process::{
+        Child,
+        Command,
+        Stdio,
+    },
+    sync::{
+        atomic::{
+            AtomicUsize,
+            Ordering,
+        },
+        Arc,
+        Mutex,
+    },
+    time::Duration,
+};
+
+use zbus::{
+    dbus_interface,
+    zvariant::{
+        ObjectPath,
+        OwnedObjectPath,
+    },
+    Connection,
+    ConnectionBuilder,
+    ObjectServer,
+    SignalContext,
+};
+
+const MANAGER_PATH: &str = "/org/freedesktop/systemd1";
+
+/// how long a job runs before systemd would report it removed
+const JOB_DURATION: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone)]
+pub struct MockUnit {
+    pub name: String,
+    pub description: String,
+    pub load_state: String,
+    pub active_state: String,
+    pub sub_state: String,
+    pub fragment_path: String,
+    pub unit_file_state: String,
+    /// what every job on this unit ends with, see JobRemoved
+    pub job_result: String,
+    /// listed by ListUnits, otherwise only a unit file until someone calls LoadUnit
+    pub loaded: bool,
+    /// MemoryCurrent of a running service
+    pub memory: u64,
+    pub drop_in_paths: Vec<String>,
+}
+
+impl MockUnit {
+    /// a loaded, inactive and disabled unit with a file in /etc/systemd/system
+    pub fn new(name: &str) -> Self {
+        MockUnit {
+            name: name.to_string(),
+            description: format!("mock {name}"),
+            load_state: "loaded".to_string(),
+            active_state: "inactive".to_string(),
+            sub_state: "dead".to_string(),
+            fragment_path: format!("/etc/systemd/system/{name}"),
+            unit_file_state: "disabled".to_string(),
+            job_result: "done".to_string(),
+            loaded: true,
+            memory: 4 << 20,
+            drop_in_paths: Vec::new(),
+        }
+    }
+
+    pub fn state(mut self, active: &str, sub: &str) -> Self {
+        self.active_state = active.to_string();
+        self.sub_state = sub.to_string();
+        self
+    }
+
+    pub fn job_result(mut self, result: &str) -> Self {
+        self.job_result = result.to_string();
+        self
+    }
+
+    pub fn unit_file_state(mut self, state: &str) -> Self {
+        self.unit_file_state = state.to_string();
+        self
+    }
+
+    pub fn memory(mut self, bytes: u64) -> Self {
+        self.memory = bytes;
+        self
+    }
+
+    /// only a unit file, systemd has not loaded it
+    pub fn unloaded(mut self) -> Self {
+        self.loaded = false;
+        self
+    }
+
+    fn path(&self) -> OwnedObjectPath {
+        unit_path(&self.name)
+    }
+}
+
+/// like systemd's bus_label_escape
+fn unit_path(name: &str) -> OwnedObjectPath {
+    let mut path = format!("{MANAGER_PATH}/unit/");
+    for b in name.bytes() {
+        match b.is_ascii_alphanumeric() {
+            true => path.push(b as char),
+            false => path.push_str(&format!("_{b:02x}")),
+        }
+    }
+    ObjectPath::try_from(path).unwrap().into()
+}
+
+/// foo@.service for foo@bar.service
+fn template_of(name: &str) -> Option<String> {
+    let (prefix, rest) = name.split_once('@')?;
+    let (instance, unit_type) = rest.rsplit_once('.')?;
+    (!instance.is_empty()).then(|| format!("{prefix}@.{unit_type}"))
+}
+
+/// the objects of a unit made after the start, like an instance
+async fn serve_unit(server: &ObjectServer, shared: &Shared, unit: MockUnit) -> zbus::Result<()> {
+    let name = unit.name.clone();
+    let path = unit.path();
+    shared.lock().unwrap().units.insert(name.clone(), unit);
+    server
+        .at(
+            &path,
+            Unit {
+                shared: shared.clone(),
+                name: name.clone(),
+            },
+        )
+        .await?;
+    if name.ends_with(".service") {
+        server
+            .at(
+                &path,
+                Service {
+                    shared: shared.clone(),
+                    name,
+                },
+            )
+            .await?;
+    } else if name.ends_with(".timer") {
+        server.at(&path, Timer { name }).await?;
+    }
+    Ok(())
+}
+
+/// the service a timer or socket of the same name starts
+fn triggered(name: &str) -> Option<String> {
+    let (base, unit_type) = name.rsplit_once('.')?;
+    matches!(unit_type, "timer" | "socket").then(|| format!("{base}.service"))
+}
+
+fn job_path(id: u32) -> OwnedObjectPath {
+    ObjectPath::try_from(format!("{MANAGER_PATH}/job/{id}"))
+        .unwrap()
+        .into()
+}
+
+#[derive(Default)]
+struct Inner {
+    units: BTreeMap<String, MockUnit>,
+    /// method calls that change something, like "Start foo.service replace"
+    calls: Vec<String>,
+    next_job: u32,
+    /// (uid, name, state) of who logind says has a manager
+    users: Vec<(u32, String, String)>,
+}
+
+type Shared = Arc<Mutex<Inner>>;
+
+#[derive(Debug, zbus::DBusError)]
+#[dbus_error(prefix = "org.freedesktop.systemd1")]
+enum Error {
+    #[dbus_error(zbus_error)]
+    ZBus(zbus::Error),
+    NoSuchUnit(String),
+}
+
+struct Manager {
+    shared: Shared,
+}
+
+type ListUnitsItem = (
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    OwnedObjectPath,
+    u32,
+    String,
+    OwnedObjectPath,
+);
+type UnitFileChange = (String, String, String);
+
+#[dbus_interface(name = "org.freedesktop.systemd1.Manager")]
+impl Manager {
+    fn subscribe(&self) {}
+
+    fn reload(&self) {
+        self.shared.lock().unwrap().calls.push("Reload".to_string());
+    }
+
+    fn list_units(&self) -> Vec<ListUnitsItem> {
+        let inner = self.shared.lock().unwrap();
+        inner
+            .units
+            .values()
+            .filter(|unit| unit.loaded)
+            .map(|unit| {
+                (
+                    unit.name.clone(),
+                    unit.description.clone(),
+                    unit.load_state.clone(),
+                    unit.active_state.clone(),
+                    unit.sub_state.clone(),
+                    String::new(),
+                    unit.path(),
+                    0,
+                    String::new(),
+                    ObjectPath::try_from("/").unwrap().into(),
+                )
+            })
+            .collect()
+    }
+
+    fn list_unit_files(&self) -> Vec<(String, String)> {
+        let inner = self.shared.lock().unwrap();
+        inner
+            .units
+            .values()
+            .filter(|unit| !unit.fragment_path.is_empty())
+            .map(|unit| (unit.fragment_path.clone(), unit.unit_file_state.clone()))
+            .collect()
+    }
+
+    fn get_unit(&self, name: String) -> Result<OwnedObjectPath, Error> {
+        let inner = self.shared.lock().unwrap();
+        match inner.units.get(&name) {
+            Some(unit) if unit.loaded => Ok(unit.path()),
+            _ => Err(Error::NoSuchUnit(format!("Unit {name} not loaded."))),
+        }
+    }
+
+    async fn load_unit(
+        &self,
+        name: String,
+        #[zbus(object_server)] server: &ObjectServer,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> Result<OwnedObjectPath, Error> {
+        // an instance of a template is made on first load, like foo@bar.service of foo@.service
+        let instance = {
+            let inner = self.shared.lock().unwrap();
+            match (inner.units.contains_key(&name), template_of(&name)) {
+                (false, Some(template)) => inner.units.get(&template).map(|template| MockUnit {
+                    name: name.clone(),
+                    description: format!("mock {name}"),
+                    loaded: false,
+                    ..template.clone()
+                }),
+                _ => None,
+            }
+        };
+        if let Some(instance) = instance {
+            serve_unit(server, &self.shared, instance).await?;
+        }
+
+        let (path, new) = {
+            let mut inner = self.shared.lock().unwrap();
+            let Some(unit) = inner.units.get_mut(&name) else {
+                return Err(Error::NoSuchUnit(format!("Unit {name} not found.")));
+            };
+            let new = !unit.loaded;
+            unit.loaded = true;
+            (unit.path(), new)
+        };
+        if new {
+            Manager::unit_new(&ctxt, &name, path.as_ref()).await?;
+        }
+        Ok(path)
+    }
+
+    fn enable_unit_files(
+        &self,
+        files: Vec<String>,
+        runtime: bool,
+        force: bool,
+    ) -> (bool, Vec<UnitFileChange>) {
+        let _ = (runtime, force);
+        let changes = self.set_unit_file_state("EnableUnitFiles", &files, "enabled");
+        (false, changes)
+    }
+
+    fn reenable_unit_files(
+        &self,
+        files: Vec<String>,
+        runtime: bool,
+        force: bool,
+    ) -> (bool, Vec<UnitFileChange>) {
+        let _ = (runtime, force);
+        let changes = self.set_unit_file_state("ReenableUnitFiles", &files, "enabled");
+        (false, changes)
+    }
+
+    fn disable_unit_files(&self, files: Vec<String>, runtime: bool) -> Vec<UnitFileChange> {
+        let _ = runtime;
+        self.set_unit_file_state("DisableUnitFiles", &files, "disabled")
+    }
+
+    fn mask_unit_files(
+        &self,
+        files: Vec<String>,
+        runtime: bool,
+        force: bool,
+    ) -> Vec<UnitFileChange> {
+        let _ = force;
+        self.set_mask("MaskUnitFiles", &files, runtime, true)
+    }
+
+    fn unmask_unit_files(&self, files: Vec<String>, runtime: bool) -> Vec<UnitFileChange> {
+        self.set_mask("UnmaskUnitFiles", &files, runtime, false)
+    }
+
+    #[dbus_interface(signal)]
+    async fn unit_new(ctxt: &SignalContext<'_>, id: &str, unit: ObjectPath<'_>)
+        -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn unit_removed(
+        ctxt: &SignalContext<'_>,
+        id: &str,
+        unit: ObjectPath<'_>,
+    ) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn job_new(
+        ctxt: &SignalContext<'_>,
+        id: u32,
+        job: ObjectPath<'_>,
+        unit: &str,
+    ) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn job_removed(
+        ctxt: &SignalContext<'_>,
+        id: u32,
+        job: ObjectPath<'_>,
+        unit: &str,
+        result: &str,
+    ) -> zbus::Result<()>;
+}
+
+impl Manager {
+    /// files are paths or names, like systemd takes them
+    /// a link to /dev/null in /etc, or in /run for `runtime`
+    fn set_mask(
+        &self,
+        method: &str,
+        files: &[String],
+        runtime: bool,
+        mask: bool,
+    ) -> Vec<UnitFileChange> {
+        let mut inner = self.shared.lock().unwrap();
+        let (dir, state) = match runtime {
+            true => ("/run/systemd/system", "masked-runtime"),
+            false => ("/etc/systemd/system", "masked"),
+        };
+        let mut changes = Vec::new();
+        for file in files {
+            inner.calls.push(match runtime {
+                true => format!("{method} {file} runtime"),
+                false => format!("{method} {file}"),
+            });
+            let Some(unit) = inner.units.get_mut(file) else {
+                continue;
+            };
+            let link = format!("{dir}/{}", unit.name);
+            if mask {
+                unit.unit_file_state = state.to_string();
+                changes.push(("symlink".to_string(), link, "/dev/null".to_string()));
+            } else if unit.unit_file_state == state {
+                unit.unit_file_state = "disabled".to_string();
+                changes.push(("unlink".to_string(), link, String::new()));
+            }
+        }
+        changes
+    }
+
+    fn set_unit_file_state(
+        &self,
+        method: &str,
+        files: &[String],
+        state: &str,
+    ) -> Vec<UnitFileChange> {
+        let mut inner = self.shared.lock().unwrap();
+        let mut changes = Vec::new();
+        for file in files {
+            inner.calls.push(format!("{method} {file}"));
+            let unit = inner
+                .units
+                .values_mut()
+                .find(|unit| unit.fragment_path == *file || unit.name == *file);
+            if let Some(unit) = unit {
+                unit.unit_file_state = state.to_string();
+                let link = format!("/etc/systemd/system/multi-user.target.wants/{}", unit.name);
+                changes.push(match state {
+                    "enabled" => ("symlink".to_string(), link, unit.fragment_path.clone()),
+                    _ => ("unlink".to_string(), link, String::new()),
+                });
+            }
+        }
+        changes
+    }
+}
+
+struct Unit {
+    shared: Shared,
+    name: String,
+}
+
+impl Unit {
+    fn get<T>(&self, f: impl FnOnce(&MockUnit) -> T) -> T {
+        f(&self.shared.lock().unwrap().units[&self.name])
+    }
+
+    /// queue a job, it runs on its own thread like systemd runs it on its own time
+    fn queue(&self, conn: &Connection, job_type: &'static str, mode: &str) -> OwnedObjectPath {
+        let id = {
+            let mut inner = self.shared.lock().unwrap();
+            inner.calls.push(format!(
+                "{}{} {} {mode}",
+                job_type[..1].to_uppercase(),
+                &job_type[1..],
+                self.name
+            ));
+            inner.next_job += 1;
+            inner.next_job
+        };
+
+        let (conn, shared, name) = (conn.clone(), self.shared.clone(), self.name.clone());
+        std::thread::spawn(move || {
+            futures::executor::block_on(run_job(conn, shared, name, job_type, id))
+                .expect("mock job failed");
+        });
+        job_path(id)
+    }
+}
+
+#[dbus_interface(name = "org.freedesktop.systemd1.Unit")]
+impl Unit {
+    fn start(&self, mode: String, #[zbus(connection)] conn: &Connection) -> OwnedObjectPath {
+        self.queue(conn, "start", &mode)
+    }
+
+    fn stop(&self, mode: String, #[zbus(connection)] conn: &Connection) -> OwnedObjectPath {
+        self.queue(conn, "stop", &mode)
+    }
+
+    fn restart(&self, mode: String, #[zbus(connection)] conn: &Connection) -> OwnedObjectPath {
+        self.queue(conn, "restart", &mode)
+    }
+
+    #[dbus_interface(property)]
+    fn id(&self) -> String {
+        self.name.clone()
+    }
+
+    #[dbus_interface(property)]
+    fn names(&self) -> Vec<String> {
+        vec![self.name.clone()]
+    }
+
+    #[dbus_interface(property)]
+    fn description(&self) -> String {
+        self.get(|unit| unit.description.clone())
+    }
+
+    #[dbus_interface(property)]
+    fn load_state(&self) -> String {
+        self.get(|unit| unit.load_state.clone())
+    }
+
+    #[dbus_interface(property)]
+    fn active_state(&self) -> String {
+        self.get(|unit| unit.active_state.clone())
+    }
+
+    #[dbus_interface(property)]
+    fn sub_state(&self) -> String {
+        self.get(|unit| unit.sub_state.clone())
+    }
+
+    #[dbus_interface(property)]
+    fn fragment_path(&self) -> String {
+        self.get(|unit| unit.fragment_path.clone())
+    }
+
+    #[dbus_interface(property)]
+    fn unit_file_state(&self) -> String {
+        self.get(|unit| unit.unit_file_state.clone())
+    }
+
+    #[dbus_interface(property)]
+    fn unit_file_preset(&self) -> String {
+        "enabled".to_string()
+    }
+
+    #[dbus_interface(property)]
+    fn drop_in_paths(&self) -> Vec<String> {
+        self.get(|unit| unit.drop_in_paths.clone())
+    }
+
+    #[dbus_interface(property)]
+    fn triggers(&self) -> Vec<String> {
+        triggered(&self.name).into_iter().collect()
+    }
+
+    #[dbus_interface(property)]
+    fn documentation(&self) -> Vec<String> {
+        vec![format!("man:{}(8)", self.name)]
+    }
+
+    #[dbus_interface(property)]
+    fn wants(&self) -> Vec<String> {
+        vec!["network-online.target".to_string()]
+    }
+
+    #[dbus_interface(property)]
+    fn after(&self) -> Vec<String> {
+        vec![
+            "network-online.target".to_string(),
+            "basic.target".to_string(),
+        ]
+    }
+
+    #[dbus_interface(property)]
+    fn conditions(&self) -> Vec<(String, bool, bool, String, i32)> {
+        vec![(
+            "ConditionPathExists".to_string(),
+            false,
+            true,
+            "/etc/mock/disabled".to_string(),
+            0,
+        )]
+    }
+}
+
+struct Service {
+    shared: Shared,
+    name: String,
+}
+
+impl Service {
+    fn running(&self) -> bool {
+        self.shared.lock().unwrap().units[&self.name].sub_state == "running"
+    }
+}
+
+#[dbus_interface(name = "org.freedesktop.systemd1.Service")]
+impl Service {
+    fn get_processes(&self) -> Vec<(String, u32, String)> {
+        match self.running() {
+            true => vec![(
+                self.control_group(),
+                4242,
+                format!("/usr/bin/{}", self.name),
+            )],
+            false => Vec::new(),
+        }
+    }
+
+    #[dbus_interface(property, name = "MainPID")]
+    fn main_pid(&self) -> u32 {
+        match self.running() {
+            true => 4242,
+            false => 0,
+        }
+    }
+
+    #[dbus_interface(property)]
+    fn control_group(&self) -> String {
+        format!("/system.slice/{}", self.name)
+    }
+
+    #[dbus_interface(property)]
+    fn tasks_current(&self) -> u64 {
+        1
+    }
+
+    #[dbus_interface(property)]
+    fn memory_current(&self) -> u64 {
+        // systemd has nothing to account for without processes
+        match self.running() {
+            true => self.shared.lock().unwrap().units[&self.name].memory,
+            false => u64::MAX,
+        }
+    }
+
+    #[dbus_interface(property, name = "CPUUsageNSec")]
+    fn cpu_usage_n_sec(&self) -> u64 {
+        u64::MAX
+    }
+
+    #[dbus_interface(property, name = "Type")]
+    fn type_(&self) -> String {
+        "simple".to_string()
+    }
+
+    #[dbus_interface(property)]
+    fn remain_after_exit(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn restart(&self) -> String {
+        "on-failure".to_string()
+    }
+
+    #[dbus_interface(property, name = "TimeoutStartUSec")]
+    fn timeout_start_usec(&self) -> u64 {
+        90_000_000
+    }
+
+    #[dbus_interface(property)]
+    fn environment(&self) -> Vec<String> {
+        vec!["MODE=mock".to_string(), "GREETING=hello world".to_string()]
+    }
+
+    #[dbus_interface(property, name = "LimitNOFILE")]
+    fn limit_nofile(&self) -> u64 {
+        524288
+    }
+
+    #[dbus_interface(property, name = "LimitNOFILESoft")]
+    fn limit_nofile_soft(&self) -> u64 {
+        1024
+    }
+
+    /// path, argv, ignore failure, then the times, pid and exit status of the last run
+    #[allow(clippy::type_complexity)]
+    #[dbus_interface(property)]
+    fn exec_start(&self) -> Vec<(String, Vec<String>, bool, u64, u64, u64, u64, u32, i32, i32)> {
+        let path = format!("/usr/bin/{}", self.name);
+        vec![(
+            path.clone(),
+            vec![path, "--serve".to_string()],
+            false,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        )]
+    }
+}
+
+struct Timer {
+    name: String,
+}
+
+#[dbus_interface(name = "org.freedesktop.systemd1.Timer")]
+impl Timer {
+    #[dbus_interface(property)]
+    fn unit(&self) -> String {
+        triggered(&self.name).unwrap_or_default()
+    }
+}
+
+struct Job {
+    id: u32,
+    job_type: &'static str,
+    unit: String,
+}
+
+#[dbus_interface(name = "org.freedesktop.systemd1.Job")]
+impl Job {
+    #[dbus_interface(property)]
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    #[dbus_interface(property)]
+    fn job_type(&self) -> String {
+        self.job_type.to_string()
+    }
+
+    #[dbus_interface(property)]
+    fn state(&self) -> String {
+        "running".to_string()
+    }
+
+    #[dbus_interface(property)]
+    fn unit(&self) -> (String, OwnedObjectPath) {
+        (self.unit.clone(), unit_path(&self.unit))
+    }
+}
+
+const LOGIN_PATH: &str = "/org/freedesktop/login1";
+
+fn login_user_path(uid: u32) -> OwnedObjectPath {
+    ObjectPath::try_from(format!("{LOGIN_PATH}/user/_{uid}"))
+        .unwrap()
+        .into()
+}
+
+/// logind with users and no sessions, enough for --all-users
+struct Login {
+    shared: Shared,
+}
+
+#[dbus_interface(name = "org.freedesktop.login1.Manager")]
+impl Login {
+    fn list_users(&self) -> Vec<(u32, String, OwnedObjectPath)> {
+        let inner = self.shared.lock().unwrap();
+        inner
+            .users
+            .iter()
+            .map(|(uid, name, _)| (*uid, name.clone(), login_user_path(*uid)))
+            .collect()
+    }
+
+    fn list_sessions(&self) -> Vec<(String, u32, String, String, OwnedObjectPath)> {
+        Vec::new()
+    }
+}
+
+struct LoginUser {
+    state: String,
+}
+
+#[dbus_interface(name = "org.freedesktop.login1.User")]
+impl LoginUser {
+    #[dbus_interface(property)]
+    fn state(&self) -> String {
+        self.state.clone()
+    }
+}
+
+/// the life of a job: JobNew, a transitional state, the end state, JobRemoved
+async fn run_job(
+    conn: Connection,
+    shared: Shared,
+    unit: String,
+    job_type: &'static str,
+    id: u32,
+) -> zbus::Result<()> {
+    let server = conn.object_server();
+    let path = job_path(id);
+    server
+        .at(
+            &path,
+            Job {
+                id,
+                job_type,
+                unit: unit.clone(),
+            },
+        )
+        .await?;
+    let manager = SignalContext::new(&conn, MANAGER_PATH)?;
+    Manager::job_new(&manager, id, path.as_ref(), &unit).await?;
+
+    let (transition, done) = match job_type {
+        "stop" => (("deactivating", "stop"), ("inactive", "dead")),
+        _ => (("activating", "start"), ("active", "running")),
+    };
+    set_state(&conn, &shared, &unit, transition).await?;
+    std::thread::sleep(JOB_DURATION);
+
+    let result = shared.lock().unwrap().units[&unit].job_result.clone();
+    let end = match result.as_str() {
+        "done" => done,
+        _ => ("failed", "failed"),
+    };
+    set_state(&conn, &shared, &unit, end).await?;
+
+    server.remove::<Job, _>(&path).await?;
+    Manager::job_removed(&manager, id, path.as_ref(), &unit, &result).await
+}
+
+async fn set_state(
+    conn: &Connection,
+    shared: &Shared,
+    unit: &str,
+    (active, sub): (&str, &str),
+) -> zbus::Result<()> {
+    {
+        let mut inner = shared.lock().unwrap();
+        let unit = inner.units.get_mut(unit).unwrap();
+        unit.active_state = active.to_string();
+        unit.sub_state = sub.to_string();
+    }
+    let iface = conn
+        .object_server()
+        .interface::<_, Unit>(unit_path(unit))
+        .await?;
+    let object = iface.get().await;
+    object.active_state_changed(iface.signal_context()).await?;
+    object.sub_state_changed(iface.signal_context()).await
+}
+
+/// a dbus-daemon of our own with a fake systemd on it, both gone on drop
+pub struct MockSystemd {
+    pub address: String,
+    daemon: Child,
+    dir: PathBuf,
+    conn: Connection,
+    shared: Shared,
+}
+
+const BUS_CONFIG: &str = r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>session</type>
+  <listen>unix:path=BUS_PATH</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow send_destination="*" eavesdrop="true"/>
+    <allow eavesdrop="true"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>
+"#;
+
+impl MockSystemd {
+    /// panics if there is no dbus-daemon to run the bus with
+    pub async fn start(units: impl IntoIterator<Item = MockUnit>) -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "sys-mock-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("bus.conf");
+        let bus_path = dir.join("bus");
+        std::fs::write(
+            &config,
+            BUS_CONFIG.replace("BUS_PATH", bus_path.to_str().unwrap()),
+        )
+        .unwrap();
+
+        let mut daemon = match Command::new("dbus-daemon")
+            .arg("--nofork")
+            .arg("--print-address")
+            .arg(format!("--config-file={}", config.display()))
+            .stdout(Stdio::piped())
+            // complains about fd limits and such when not run as a real session bus
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(daemon) => daemon,
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(&dir);
+                panic!("could not run dbus-daemon, the tests need it for their bus: {e}");
+            }
+        };
+        // the address is printed once the bus listens
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        let address = address.trim().to_string();
+
+        let shared: Shared = Default::default();
+        let conn = Self::serve(&address, &shared, units).await.unwrap();
+
+        MockSystemd {
+            address,
+            daemon,
+            dir,
+            conn,
+            shared,
+        }
+    }
+
+    async fn serve(
+        address: &str,
+        shared: &Shared,
+        units: impl IntoIterator<Item = MockUnit>,
+    ) -> zbus::Result<Connection> {
+        let mut builder = ConnectionBuilder::address(address)?
+            .name("org.freedesktop.systemd1")?
+            .name("org.freedesktop.login1")?
+            .serve_at(
+                MANAGER_PATH,
+                Manager {
+                    shared: shared.clone(),
+                },
+            )?
+            .serve_at(
+                LOGIN_PATH,
+                Login {
+                    shared: shared.clone(),
+                },
+            )?;
+
+        // every unit gets its objects now, LoadUnit only has to flip `loaded`
+        for unit in units {
+            let name = unit.name.clone();
+            builder = builder.serve_at(
+                unit.path(),
+                Unit {
+                    shared: shared.clone(),
+                    name: name.clone(),
+                },
+            )?;
+            if name.ends_with(".service") {
+                builder = builder.serve_at(
+                    unit.path(),
+                    Service {
+                        shared: shared.clone(),
+                        name: name.clone(),
+                    },
+                )?;
+            } else if name.ends_with(".timer") {
+                builder = builder.serve_at(unit.path(), Timer { name: name.clone() })?;
+            }
+            shared.lock().unwrap().units.insert(name, unit);
+        }
+        builder.build().await
+    }
+
+    pub fn unit(&self, name: &str) -> MockUnit {
+        self.shared.lock().unwrap().units[name].clone()
+    }
+
+    pub fn calls(&self) -> Vec<String> {
+        self.shared.lock().unwrap().calls.clone()
+    }
+
+    /// the bus socket, for a path to point at it
+    pub fn bus_path(&self) -> PathBuf {
+        self.dir.join("bus")
+    }
+
+    /// have logind on this bus list a user, with state like "active" or "closing"
+    pub async fn log_in(&self, uid: u32, name: &str, state: &str) {
+        self.conn
+            .object_server()
+            .at(
+                login_user_path(uid),
+                LoginUser {
+                    state: state.to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        self.shared
+            .lock()
+            .unwrap()
+            .users
+            .push((uid, name.to_string(), state.to_string()));
+    }
+}
+
+impl Drop for MockSystemd {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}